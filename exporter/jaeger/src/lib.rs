@@ -0,0 +1,257 @@
+/*!
+Package jaeger contains an exporter for Jaeger.
+
+The exporter encodes spans as Jaeger Thrift over the compact protocol and
+sends them over UDP to a Jaeger agent, usually running on the same host. Wrap
+it in a BatchExporter so that spans are sent in batches from a background
+thread:
+
+```no_run
+use std::sync::Arc;
+
+use opencensus_jaeger::{AgentConfig, JaegerExporter, Process};
+use opencensus_trace::{register_exporter, shutdown_exporters, BatchConfig, BatchExporter};
+
+let exporter = JaegerExporter::new(Process::new("frontend"), AgentConfig::default()).unwrap();
+register_exporter(Arc::new(BatchExporter::new(
+    Arc::new(exporter),
+    BatchConfig::default(),
+)));
+
+// ...
+
+shutdown_exporters().unwrap();
+```
+
+Batches that don't fit in a single UDP packet are split over several packets.
+Links map to span references, annotations and message events to logs, and
+attributes to typed tags.
+*/
+#![warn(missing_docs, rust_2018_idioms, clippy::all)]
+
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use opencensus_trace::{ExportError, SpanData, SpanExporter};
+
+mod model;
+mod thrift;
+
+pub use crate::model::Process;
+
+/// DEFAULT_AGENT_ENDPOINT is the address of a Jaeger agent running locally.
+pub const DEFAULT_AGENT_ENDPOINT: &str = "localhost:6831";
+/// DEFAULT_MAX_PACKET_SIZE is the largest UDP packet the Jaeger agent accepts
+/// by default.
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 65000;
+
+/// AgentConfig configures how a JaegerExporter reaches the Jaeger agent.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct AgentConfig {
+    /// agent_endpoint is the host and port of the agent's compact Thrift
+    /// UDP endpoint.
+    pub agent_endpoint: String,
+    /// max_packet_size is the size of the largest packet sent to the agent,
+    /// it must not exceed the agent's own limit.
+    pub max_packet_size: usize,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        AgentConfig {
+            agent_endpoint: DEFAULT_AGENT_ENDPOINT.to_string(),
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+        }
+    }
+}
+
+/// JaegerExporter is a SpanExporter that sends spans to a Jaeger agent.
+pub struct JaegerExporter {
+    socket: UdpSocket,
+    process: Vec<u8>,
+    max_packet_size: usize,
+    // the size of a packet without any spans.
+    overhead: usize,
+    seq_no: AtomicI64,
+}
+
+impl JaegerExporter {
+    /// new returns an exporter that sends spans recorded by process to the
+    /// agent in config. IPv4 addresses of the agent are preferred.
+    pub fn new(process: Process, config: AgentConfig) -> io::Result<Self> {
+        let addrs: Vec<_> = config.agent_endpoint.to_socket_addrs()?.collect();
+        let addr = addrs
+            .iter()
+            .find(|addr| addr.is_ipv4())
+            .or_else(|| addrs.first())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("no addresses for {}", config.agent_endpoint),
+                )
+            })?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+
+        let process = model::encode_process(&process);
+        // a full list header and sequence number take at most 5 and 10 bytes.
+        let overhead = model::encode_batch(&process, &[], 0).len() + 5 + 10;
+        Ok(JaegerExporter {
+            socket,
+            process,
+            max_packet_size: config.max_packet_size,
+            overhead,
+            seq_no: AtomicI64::new(0),
+        })
+    }
+
+    fn send(&self, spans: &[Vec<u8>]) -> io::Result<()> {
+        let seq_no = self.seq_no.fetch_add(1, Ordering::Relaxed);
+        let packet = model::encode_batch(&self.process, spans, seq_no);
+        self.socket.send(&packet)?;
+        Ok(())
+    }
+}
+
+impl SpanExporter for JaegerExporter {
+    /// export sends spans in as few packets as possible. Spans that don't fit
+    /// in a packet on their own are dropped, and every packet is sent even if
+    /// sending one fails.
+    fn export(&self, spans: &[SpanData]) -> Result<(), ExportError> {
+        let mut result = Ok(());
+        let mut too_large = 0;
+        let mut packet = Vec::new();
+        let mut size = self.overhead;
+        for s in spans {
+            let span = model::encode_span(s);
+            if self.overhead + span.len() > self.max_packet_size {
+                too_large += 1;
+                continue;
+            }
+            if size + span.len() > self.max_packet_size {
+                if let Err(err) = self.send(&packet) {
+                    result = result.and(Err(ExportError::other(err)));
+                }
+                packet.clear();
+                size = self.overhead;
+            }
+            size += span.len();
+            packet.push(span);
+        }
+        if !packet.is_empty() {
+            if let Err(err) = self.send(&packet) {
+                result = result.and(Err(ExportError::other(err)));
+            }
+        }
+
+        if too_large > 0 {
+            result = result.and(Err(ExportError::other(format!(
+                "{} spans are larger than the max packet size of {} bytes",
+                too_large, self.max_packet_size
+            ))));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use opencensus_trace::attributes;
+    use opencensus_trace::test_util::span_data;
+
+    use crate::thrift::CompactReader;
+
+    fn mock_agent() -> (UdpSocket, AgentConfig) {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        agent
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let config = AgentConfig {
+            agent_endpoint: agent.local_addr().unwrap().to_string(),
+            ..AgentConfig::default()
+        };
+        (agent, config)
+    }
+
+    // receive returns the size, sequence number and span names of the next
+    // packet sent to agent.
+    fn receive(agent: &UdpSocket) -> (usize, i64, Vec<String>) {
+        let mut buf = [0; 65536];
+        let n = agent.recv(&mut buf).unwrap();
+        let mut r = CompactReader::new(&buf[..n]);
+        let (name, _, _) = r.read_message_begin();
+        assert_eq!(name, "emitBatch");
+        let batch = r.read_struct().field(1).unwrap().clone();
+        assert!(r.is_empty());
+
+        assert_eq!(batch.field(1).unwrap().string(1), "frontend");
+        let names = batch
+            .list(2)
+            .iter()
+            .map(|s| s.string(5).to_string())
+            .collect();
+        (n, batch.int(3), names)
+    }
+
+    #[test]
+    fn sends_batches_to_agent() {
+        let (agent, config) = mock_agent();
+        let exporter = JaegerExporter::new(Process::new("frontend"), config).unwrap();
+
+        exporter.export(&[span_data("a"), span_data("b")]).unwrap();
+        exporter.export(&[span_data("c")]).unwrap();
+
+        assert_eq!(receive(&agent).1, 0);
+        let (_, seq_no, names) = receive(&agent);
+        assert_eq!(seq_no, 1);
+        assert_eq!(names, vec!["c"]);
+    }
+
+    #[test]
+    fn splits_batches_by_packet_size() {
+        let (agent, mut config) = mock_agent();
+        config.max_packet_size = 200;
+        let exporter = JaegerExporter::new(Process::new("frontend"), config).unwrap();
+
+        let spans: Vec<_> = (0..10).map(|i| span_data(&format!("span-{}", i))).collect();
+        exporter.export(&spans).unwrap();
+
+        let mut names = Vec::new();
+        while names.len() < spans.len() {
+            let (size, _, packet_names) = receive(&agent);
+            assert!(size <= 200, "packet of {} bytes", size);
+            assert!(!packet_names.is_empty());
+            names.extend(packet_names);
+        }
+        let want: Vec<_> = spans.iter().map(|s| s.name.clone()).collect();
+        assert_eq!(names, want);
+    }
+
+    #[test]
+    fn drops_spans_larger_than_a_packet() {
+        let (agent, mut config) = mock_agent();
+        config.max_packet_size = 200;
+        let exporter = JaegerExporter::new(Process::new("frontend"), config).unwrap();
+
+        let mut large = span_data("large");
+        large.attributes = attributes! { "payload" => "x".repeat(200) };
+        let err = exporter
+            .export(&[span_data("a"), large, span_data("b")])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "export failed: 1 spans are larger than the max packet size of 200 bytes"
+        );
+        assert_eq!(receive(&agent).2, vec!["a", "b"]);
+    }
+}
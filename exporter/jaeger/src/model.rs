@@ -0,0 +1,459 @@
+// Encodes spans as the structs of jaeger.thrift and the emitBatch call of
+// agent.thrift, see https://github.com/jaegertracing/jaeger-idl
+
+use std::time::SystemTime;
+
+use opencensus_trace::{
+    epoch_micros, AttributeValue, Attributes, LinkType, MessageEventType, SpanData, SpanKind,
+    StatusCode,
+};
+
+use crate::thrift::{CompactWriter, MESSAGE_ONEWAY, TYPE_LIST, TYPE_STRUCT};
+
+const SPAN_KIND_TAG: &str = "span.kind";
+const STATUS_CODE_TAG: &str = "status.code";
+const STATUS_MESSAGE_TAG: &str = "status.message";
+const ERROR_TAG: &str = "error";
+const MESSAGE_FIELD: &str = "message";
+const MESSAGE_TYPE_FIELD: &str = "message.type";
+const MESSAGE_ID_FIELD: &str = "message.id";
+const MESSAGE_UNCOMPRESSED_SIZE_FIELD: &str = "message.uncompressed_size";
+const MESSAGE_COMPRESSED_SIZE_FIELD: &str = "message.compressed_size";
+
+// TagType
+const TAG_STRING: i32 = 0;
+const TAG_DOUBLE: i32 = 1;
+const TAG_BOOL: i32 = 2;
+const TAG_LONG: i32 = 3;
+const TAG_BINARY: i32 = 4;
+
+// SpanRefType
+const CHILD_OF: i32 = 0;
+const FOLLOWS_FROM: i32 = 1;
+
+/// Process describes the process that records the spans.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Process {
+    /// service_name is the name of the service, e.g. "frontend".
+    pub service_name: String,
+    /// tags are attributes of the process, e.g. its hostname or version.
+    pub tags: Attributes,
+}
+
+impl Process {
+    /// new returns a Process for the named service.
+    pub fn new(service_name: &str) -> Self {
+        Process {
+            service_name: service_name.to_string(),
+            tags: Attributes::new(),
+        }
+    }
+}
+
+/// encode_process returns the Process struct for p.
+pub fn encode_process(p: &Process) -> Vec<u8> {
+    let mut w = CompactWriter::new();
+    w.write_struct_begin();
+    w.write_string_field(1, &p.service_name);
+    write_tags(&mut w, 2, sorted(&p.tags));
+    w.write_struct_end();
+    w.into_bytes()
+}
+
+/// encode_span returns the Span struct for s.
+pub fn encode_span(s: &SpanData) -> Vec<u8> {
+    let mut w = CompactWriter::new();
+    let (trace_id_high, trace_id_low) = split_trace_id(&s.span_context.trace_id.0);
+
+    w.write_struct_begin();
+    w.write_i64_field(1, trace_id_low);
+    w.write_i64_field(2, trace_id_high);
+    w.write_i64_field(3, i64::from_be_bytes(s.span_context.span_id.0));
+    w.write_i64_field(4, s.parent_span_id.map_or(0, |id| i64::from_be_bytes(id.0)));
+    w.write_string_field(5, &s.name);
+    if !s.links.is_empty() {
+        w.write_field_begin(TYPE_LIST, 6);
+        w.write_list_begin(TYPE_STRUCT, s.links.len());
+        for link in &s.links {
+            let (high, low) = split_trace_id(&link.trace_id.0);
+            w.write_struct_begin();
+            w.write_i32_field(1, ref_type(&link._type));
+            w.write_i64_field(2, low);
+            w.write_i64_field(3, high);
+            w.write_i64_field(4, i64::from_be_bytes(link.span_id.0));
+            w.write_struct_end();
+        }
+    }
+    w.write_i32_field(7, if s.span_context.is_sampled() { 1 } else { 0 });
+    let start = epoch_micros(s.start_time);
+    let end = s.end_time.map_or(start, epoch_micros);
+    w.write_i64_field(8, start as i64);
+    w.write_i64_field(9, end.saturating_sub(start) as i64);
+    write_tags(&mut w, 10, span_tags(s));
+    write_logs(&mut w, 11, s);
+    w.write_struct_end();
+    w.into_bytes()
+}
+
+/// encode_batch returns an emitBatch call of the Agent service, with an
+/// encoded Process and Spans.
+pub fn encode_batch(process: &[u8], spans: &[Vec<u8>], seq_no: i64) -> Vec<u8> {
+    let mut w = CompactWriter::new();
+    w.write_message_begin("emitBatch", MESSAGE_ONEWAY, 0);
+    w.write_struct_begin();
+    w.write_field_begin(TYPE_STRUCT, 1);
+    w.write_struct_begin();
+    w.write_field_begin(TYPE_STRUCT, 1);
+    w.write_raw(process);
+    w.write_field_begin(TYPE_LIST, 2);
+    w.write_list_begin(TYPE_STRUCT, spans.len());
+    for s in spans {
+        w.write_raw(s);
+    }
+    w.write_i64_field(3, seq_no);
+    w.write_struct_end();
+    w.write_struct_end();
+    w.into_bytes()
+}
+
+fn split_trace_id(id: &[u8; 16]) -> (i64, i64) {
+    let mut high = [0; 8];
+    let mut low = [0; 8];
+    high.copy_from_slice(&id[..8]);
+    low.copy_from_slice(&id[8..]);
+    (i64::from_be_bytes(high), i64::from_be_bytes(low))
+}
+
+// Jaeger has no reference from a span to its child, so links to children and
+// unspecified links are FOLLOWS_FROM.
+fn ref_type(t: &LinkType) -> i32 {
+    match t {
+        LinkType::Child => CHILD_OF,
+        LinkType::Parent | LinkType::Unspecified => FOLLOWS_FROM,
+    }
+}
+
+fn span_kind(kind: &SpanKind) -> Option<&'static str> {
+    match kind {
+        SpanKind::Server => Some("server"),
+        SpanKind::Client => Some("client"),
+        SpanKind::Producer => Some("producer"),
+        SpanKind::Consumer => Some("consumer"),
+        SpanKind::Unspecified | SpanKind::Internal => None,
+    }
+}
+
+fn span_tags(s: &SpanData) -> Vec<(&str, AttributeValue)> {
+    let mut tags = sorted(&s.attributes);
+    if let Some(kind) = span_kind(&s.span_kind) {
+        tags.push((SPAN_KIND_TAG, kind.into()));
+    }
+    if let Some(status) = &s.status {
+        if status.code != StatusCode::OK {
+            tags.push((STATUS_CODE_TAG, i32::from(status.code.clone()).into()));
+            tags.push((ERROR_TAG, true.into()));
+        }
+        if !status.message.is_empty() {
+            tags.push((STATUS_MESSAGE_TAG, status.message.as_str().into()));
+        }
+    }
+    tags
+}
+
+// Attributes are sorted by key so that the encoding is deterministic.
+fn sorted(attributes: &Attributes) -> Vec<(&str, AttributeValue)> {
+    let mut tags: Vec<_> = attributes
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.clone()))
+        .collect();
+    tags.sort_by(|a, b| a.0.cmp(b.0));
+    tags
+}
+
+fn write_tags(w: &mut CompactWriter, id: i16, tags: Vec<(&str, AttributeValue)>) {
+    if tags.is_empty() {
+        return;
+    }
+    w.write_field_begin(TYPE_LIST, id);
+    w.write_list_begin(TYPE_STRUCT, tags.len());
+    for (key, value) in &tags {
+        write_tag(w, key, value);
+    }
+}
+
+// Arrays have no tag type and are encoded as JSON strings.
+fn write_tag(w: &mut CompactWriter, key: &str, value: &AttributeValue) {
+    w.write_struct_begin();
+    w.write_string_field(1, key);
+    match value {
+        AttributeValue::StringAttribute(s) => {
+            w.write_i32_field(2, TAG_STRING);
+            w.write_string_field(3, s);
+        }
+        AttributeValue::DoubleAttribute(d) => {
+            w.write_i32_field(2, TAG_DOUBLE);
+            w.write_double_field(4, *d);
+        }
+        AttributeValue::BoolAttribute(b) => {
+            w.write_i32_field(2, TAG_BOOL);
+            w.write_bool_field(5, *b);
+        }
+        AttributeValue::Int64Attribute(i) => {
+            w.write_i32_field(2, TAG_LONG);
+            w.write_i64_field(6, *i);
+        }
+        AttributeValue::BytesAttribute(b) => {
+            w.write_i32_field(2, TAG_BINARY);
+            w.write_binary_field(7, b);
+        }
+        AttributeValue::BoolArrayAttribute(_)
+        | AttributeValue::Int64ArrayAttribute(_)
+        | AttributeValue::DoubleArrayAttribute(_)
+        | AttributeValue::StringArrayAttribute(_) => {
+            w.write_i32_field(2, TAG_STRING);
+            w.write_string_field(3, &value.to_string_value());
+        }
+    }
+    w.write_struct_end();
+}
+
+// Annotations and message events both become logs, ordered by time.
+fn write_logs(w: &mut CompactWriter, id: i16, s: &SpanData) {
+    let annotations = s.annotations.iter().map(|a| {
+        let mut fields = sorted(&a.attributes);
+        fields.push((MESSAGE_FIELD, a.message.as_str().into()));
+        (a.time, fields)
+    });
+    let events = s.message_events.iter().map(|e| {
+        let event_type = match e.event_type {
+            MessageEventType::Sent => "SENT",
+            MessageEventType::Recv => "RECV",
+            MessageEventType::Unspecified => "UNSPECIFIED",
+        };
+        let fields = vec![
+            (MESSAGE_TYPE_FIELD, event_type.into()),
+            (MESSAGE_ID_FIELD, e.message_id.into()),
+            (
+                MESSAGE_UNCOMPRESSED_SIZE_FIELD,
+                e.uncompressed_byte_size.into(),
+            ),
+            (MESSAGE_COMPRESSED_SIZE_FIELD, e.compressed_byte_size.into()),
+        ];
+        (e.time, fields)
+    });
+    let mut logs: Vec<(SystemTime, Vec<_>)> = annotations.chain(events).collect();
+    if logs.is_empty() {
+        return;
+    }
+    logs.sort_by_key(|(time, _)| *time);
+
+    w.write_field_begin(TYPE_LIST, id);
+    w.write_list_begin(TYPE_STRUCT, logs.len());
+    for (time, fields) in logs {
+        w.write_struct_begin();
+        w.write_i64_field(1, epoch_micros(time) as i64);
+        write_tags(w, 2, fields);
+        w.write_struct_end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use opencensus_trace::test_util::span_data;
+    use opencensus_trace::{
+        attributes, Annotation, Link, MessageEvent, SpanID, Status, TraceID, TraceOptions,
+    };
+
+    use crate::thrift::{CompactReader, Value};
+
+    fn at(micros: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(micros)
+    }
+
+    fn decode(b: &[u8]) -> Value {
+        let mut r = CompactReader::new(b);
+        let v = r.read_struct();
+        assert!(r.is_empty());
+        v
+    }
+
+    // tags returns the key and value of each Tag in l.
+    fn tags(l: &[Value]) -> Vec<(String, Value)> {
+        l.iter()
+            .map(|tag| {
+                let value_field = match tag.int(2) as i32 {
+                    TAG_STRING => 3,
+                    TAG_DOUBLE => 4,
+                    TAG_BOOL => 5,
+                    TAG_LONG => 6,
+                    TAG_BINARY => 7,
+                    t => panic!("unknown tag type {}", t),
+                };
+                let value = tag.field(value_field).unwrap().clone();
+                (tag.string(1).to_string(), value)
+            })
+            .collect()
+    }
+
+    fn string(s: &str) -> Value {
+        Value::Binary(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn encodes_span() {
+        let mut s = span_data("span");
+        s.parent_span_id = Some(SpanID([0, 0, 0, 0, 0, 0, 0, 9]));
+        s.span_kind = SpanKind::Client;
+        s.attributes = attributes! {
+            "bool" => true,
+            "int" => 42,
+            "string" => "value",
+            "double" => 1.5,
+            "bytes" => vec![0u8, 1, 2],
+            "ints" => vec![1i64, 2],
+        };
+        s.links = vec![
+            Link {
+                trace_id: TraceID([0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 2]),
+                span_id: SpanID([0, 0, 0, 0, 0, 0, 0, 3]),
+                _type: LinkType::Child,
+                attributes: HashMap::new(),
+            },
+            Link {
+                trace_id: TraceID([0; 16]),
+                span_id: SpanID([0, 0, 0, 0, 0, 0, 0, 4]),
+                _type: LinkType::Parent,
+                attributes: HashMap::new(),
+            },
+        ];
+        s.annotations = vec![Annotation {
+            time: at(1_200),
+            message: "cache miss".to_string(),
+            attributes: attributes! { "key" => "k" },
+        }];
+        s.message_events = vec![MessageEvent {
+            time: at(1_100),
+            event_type: MessageEventType::Sent,
+            message_id: 1,
+            uncompressed_byte_size: 10,
+            compressed_byte_size: 5,
+        }];
+        s.status = Some(Status {
+            code: StatusCode::NotFound,
+            message: "no such user".to_string(),
+        });
+
+        let got = decode(&encode_span(&s));
+        assert_eq!(got.int(1), 0x090a_0b0c_0d0e_0f10);
+        assert_eq!(got.int(2), 0x0102_0304_0506_0708);
+        assert_eq!(got.int(3), 0x0102_0304_0506_0708);
+        assert_eq!(got.int(4), 9);
+        assert_eq!(got.string(5), "span");
+        let refs: Vec<_> = got
+            .list(6)
+            .iter()
+            .map(|r| (r.int(1) as i32, r.int(2), r.int(3), r.int(4)))
+            .collect();
+        assert_eq!(refs, vec![(CHILD_OF, 2, 1, 3), (FOLLOWS_FROM, 0, 0, 4)]);
+        assert_eq!(got.int(7), 1);
+        assert_eq!(got.int(8), 1_000);
+        assert_eq!(got.int(9), 500);
+        assert_eq!(
+            tags(got.list(10)),
+            vec![
+                ("bool".to_string(), Value::Bool(true)),
+                ("bytes".to_string(), Value::Binary(vec![0, 1, 2])),
+                ("double".to_string(), Value::Double(1.5)),
+                ("int".to_string(), Value::Int(42)),
+                ("ints".to_string(), string("[1,2]")),
+                ("string".to_string(), string("value")),
+                ("span.kind".to_string(), string("client")),
+                ("status.code".to_string(), Value::Int(5)),
+                ("error".to_string(), Value::Bool(true)),
+                ("status.message".to_string(), string("no such user")),
+            ]
+        );
+
+        let logs = got.list(11);
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].int(1), 1_100);
+        assert_eq!(
+            tags(logs[0].list(2)),
+            vec![
+                ("message.type".to_string(), string("SENT")),
+                ("message.id".to_string(), Value::Int(1)),
+                ("message.uncompressed_size".to_string(), Value::Int(10)),
+                ("message.compressed_size".to_string(), Value::Int(5)),
+            ]
+        );
+        assert_eq!(logs[1].int(1), 1_200);
+        assert_eq!(
+            tags(logs[1].list(2)),
+            vec![
+                ("key".to_string(), string("k")),
+                ("message".to_string(), string("cache miss")),
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_minimal_span() {
+        let mut s = span_data("span");
+        s.span_context.trace_options = TraceOptions(0);
+        s.status = Some(Status::default());
+
+        let got = decode(&encode_span(&s));
+        assert_eq!(got.int(4), 0);
+        assert_eq!(got.int(7), 0);
+        for id in &[6, 10, 11] {
+            assert_eq!(got.field(*id), None);
+        }
+    }
+
+    #[test]
+    fn span_kinds() {
+        let kinds = [
+            (SpanKind::Unspecified, None),
+            (SpanKind::Server, Some("server")),
+            (SpanKind::Client, Some("client")),
+            (SpanKind::Producer, Some("producer")),
+            (SpanKind::Consumer, Some("consumer")),
+            (SpanKind::Internal, None),
+        ];
+        for (kind, want) in kinds.iter() {
+            assert_eq!(span_kind(kind), *want, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn encodes_batch() {
+        let mut process = Process::new("frontend");
+        process.tags = attributes! { "hostname" => "host" };
+        let spans = vec![encode_span(&span_data("a")), encode_span(&span_data("b"))];
+
+        let b = encode_batch(&encode_process(&process), &spans, 7);
+        let mut r = CompactReader::new(&b);
+        assert_eq!(
+            r.read_message_begin(),
+            ("emitBatch".to_string(), MESSAGE_ONEWAY, 0)
+        );
+        let args = r.read_struct();
+        assert!(r.is_empty());
+
+        let batch = args.field(1).unwrap();
+        let process = batch.field(1).unwrap();
+        assert_eq!(process.string(1), "frontend");
+        assert_eq!(
+            tags(process.list(2)),
+            vec![("hostname".to_string(), string("host"))]
+        );
+        let names: Vec<_> = batch.list(2).iter().map(|s| s.string(5)).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(batch.int(3), 7);
+    }
+}
@@ -0,0 +1,398 @@
+// A writer for the subset of the Thrift compact protocol the Jaeger agent
+// needs, see
+// https://github.com/apache/thrift/blob/master/doc/specs/thrift-compact-protocol.md
+
+const PROTOCOL_ID: u8 = 0x82;
+const VERSION: u8 = 1;
+
+/// MESSAGE_ONEWAY is the message type of calls without a reply.
+pub const MESSAGE_ONEWAY: u8 = 4;
+
+// The compact protocol types, booleans are written as part of the field
+// header.
+const TYPE_BOOL_TRUE: u8 = 1;
+const TYPE_BOOL_FALSE: u8 = 2;
+pub const TYPE_I32: u8 = 5;
+pub const TYPE_I64: u8 = 6;
+pub const TYPE_DOUBLE: u8 = 7;
+pub const TYPE_BINARY: u8 = 8;
+pub const TYPE_LIST: u8 = 9;
+pub const TYPE_STRUCT: u8 = 12;
+
+/// CompactWriter encodes Thrift values with the compact protocol.
+#[derive(Default)]
+pub struct CompactWriter {
+    buf: Vec<u8>,
+    last_field_id: i16,
+    // the last field IDs of the enclosing structs.
+    field_ids: Vec<i16>,
+}
+
+impl CompactWriter {
+    /// new returns an empty CompactWriter.
+    pub fn new() -> Self {
+        CompactWriter::default()
+    }
+
+    /// into_bytes returns the encoded values.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    /// write_message_begin writes the header of a call to the named method.
+    pub fn write_message_begin(&mut self, name: &str, message_type: u8, seq_id: i32) {
+        self.buf.push(PROTOCOL_ID);
+        self.buf.push((message_type << 5) | VERSION);
+        self.write_varint(u64::from(seq_id as u32));
+        self.write_string(name);
+    }
+
+    /// write_struct_begin starts a struct, its fields are written until
+    /// write_struct_end is called.
+    pub fn write_struct_begin(&mut self) {
+        self.field_ids.push(self.last_field_id);
+        self.last_field_id = 0;
+    }
+
+    /// write_struct_end writes the stop field of the current struct.
+    pub fn write_struct_end(&mut self) {
+        self.buf.push(0);
+        self.last_field_id = self.field_ids.pop().unwrap_or_default();
+    }
+
+    /// write_field_begin writes the header of a field, followed by its value.
+    pub fn write_field_begin(&mut self, field_type: u8, id: i16) {
+        let delta = id.wrapping_sub(self.last_field_id);
+        if (1..=15).contains(&delta) {
+            self.buf.push((delta as u8) << 4 | field_type);
+        } else {
+            self.buf.push(field_type);
+            self.write_varint(u64::from(zigzag32(i32::from(id))));
+        }
+        self.last_field_id = id;
+    }
+
+    /// write_list_begin writes the header of a list, followed by its elements.
+    pub fn write_list_begin(&mut self, element_type: u8, size: usize) {
+        if size < 15 {
+            self.buf.push((size as u8) << 4 | element_type);
+        } else {
+            self.buf.push(0xf0 | element_type);
+            self.write_varint(size as u64);
+        }
+    }
+
+    /// write_i32 writes v as a zigzag varint.
+    pub fn write_i32(&mut self, v: i32) {
+        self.write_varint(u64::from(zigzag32(v)));
+    }
+
+    /// write_i64 writes v as a zigzag varint.
+    pub fn write_i64(&mut self, v: i64) {
+        self.write_varint(zigzag64(v));
+    }
+
+    /// write_binary writes the length of b followed by b.
+    pub fn write_binary(&mut self, b: &[u8]) {
+        self.write_varint(b.len() as u64);
+        self.buf.extend_from_slice(b);
+    }
+
+    /// write_string writes s as UTF-8 binary.
+    pub fn write_string(&mut self, s: &str) {
+        self.write_binary(s.as_bytes());
+    }
+
+    /// write_raw appends values encoded by another CompactWriter.
+    pub fn write_raw(&mut self, b: &[u8]) {
+        self.buf.extend_from_slice(b);
+    }
+
+    /// write_bool_field writes a bool field, whose value is part of its
+    /// header.
+    pub fn write_bool_field(&mut self, id: i16, v: bool) {
+        self.write_field_begin(if v { TYPE_BOOL_TRUE } else { TYPE_BOOL_FALSE }, id);
+    }
+
+    /// write_i32_field writes an i32 field.
+    pub fn write_i32_field(&mut self, id: i16, v: i32) {
+        self.write_field_begin(TYPE_I32, id);
+        self.write_i32(v);
+    }
+
+    /// write_i64_field writes an i64 field.
+    pub fn write_i64_field(&mut self, id: i16, v: i64) {
+        self.write_field_begin(TYPE_I64, id);
+        self.write_i64(v);
+    }
+
+    /// write_double_field writes a little endian double field.
+    pub fn write_double_field(&mut self, id: i16, v: f64) {
+        self.write_field_begin(TYPE_DOUBLE, id);
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// write_binary_field writes a binary field.
+    pub fn write_binary_field(&mut self, id: i16, b: &[u8]) {
+        self.write_field_begin(TYPE_BINARY, id);
+        self.write_binary(b);
+    }
+
+    /// write_string_field writes a string field.
+    pub fn write_string_field(&mut self, id: i16, s: &str) {
+        self.write_binary_field(id, s.as_bytes());
+    }
+
+    fn write_varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.buf.push(v as u8 | 0x80);
+            v >>= 7;
+        }
+        self.buf.push(v as u8);
+    }
+}
+
+fn zigzag32(v: i32) -> u32 {
+    ((v << 1) ^ (v >> 31)) as u32
+}
+
+fn zigzag64(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+/// Value is a decoded Thrift value, used to check what was written.
+#[cfg(test)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Double(f64),
+    Binary(Vec<u8>),
+    List(Vec<Value>),
+    Struct(std::collections::BTreeMap<i16, Value>),
+}
+
+#[cfg(test)]
+impl Value {
+    pub fn field(&self, id: i16) -> Option<&Value> {
+        match self {
+            Value::Struct(fields) => fields.get(&id),
+            _ => panic!("{:?} is not a struct", self),
+        }
+    }
+
+    pub fn int(&self, id: i16) -> i64 {
+        match self.field(id) {
+            Some(Value::Int(v)) => *v,
+            v => panic!("field {} is {:?}, not an int", id, v),
+        }
+    }
+
+    pub fn string(&self, id: i16) -> &str {
+        match self.field(id) {
+            Some(Value::Binary(b)) => std::str::from_utf8(b).unwrap(),
+            v => panic!("field {} is {:?}, not a string", id, v),
+        }
+    }
+
+    pub fn list(&self, id: i16) -> &[Value] {
+        match self.field(id) {
+            Some(Value::List(l)) => l,
+            None => &[],
+            v => panic!("field {} is {:?}, not a list", id, v),
+        }
+    }
+}
+
+/// CompactReader decodes what a CompactWriter wrote.
+#[cfg(test)]
+pub struct CompactReader<'a> {
+    buf: &'a [u8],
+}
+
+#[cfg(test)]
+impl<'a> CompactReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        CompactReader { buf }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// read_message_begin returns the name, type and sequence ID of a call.
+    pub fn read_message_begin(&mut self) -> (String, u8, i32) {
+        assert_eq!(self.read_byte(), PROTOCOL_ID);
+        let b = self.read_byte();
+        assert_eq!(b & 0x1f, VERSION);
+        let seq_id = self.read_varint() as u32 as i32;
+        let name = String::from_utf8(self.read_binary()).unwrap();
+        (name, b >> 5, seq_id)
+    }
+
+    pub fn read_struct(&mut self) -> Value {
+        let mut fields = std::collections::BTreeMap::new();
+        let mut last_id = 0;
+        loop {
+            let b = self.read_byte();
+            if b == 0 {
+                return Value::Struct(fields);
+            }
+            let field_type = b & 0x0f;
+            let id = match b >> 4 {
+                0 => unzigzag(self.read_varint()) as i16,
+                delta => last_id + i16::from(delta),
+            };
+            let value = match field_type {
+                TYPE_BOOL_TRUE => Value::Bool(true),
+                TYPE_BOOL_FALSE => Value::Bool(false),
+                t => self.read_value(t),
+            };
+            fields.insert(id, value);
+            last_id = id;
+        }
+    }
+
+    fn read_value(&mut self, value_type: u8) -> Value {
+        match value_type {
+            TYPE_I32 | TYPE_I64 => Value::Int(unzigzag(self.read_varint())),
+            TYPE_DOUBLE => {
+                let mut b = [0; 8];
+                b.copy_from_slice(&self.buf[..8]);
+                self.buf = &self.buf[8..];
+                Value::Double(f64::from_le_bytes(b))
+            }
+            TYPE_BINARY => Value::Binary(self.read_binary()),
+            TYPE_LIST => {
+                let b = self.read_byte();
+                let size = match b >> 4 {
+                    15 => self.read_varint() as usize,
+                    size => usize::from(size),
+                };
+                let elements = (0..size).map(|_| self.read_value(b & 0x0f)).collect();
+                Value::List(elements)
+            }
+            TYPE_STRUCT => self.read_struct(),
+            t => panic!("unexpected type {}", t),
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let b = self.buf[0];
+        self.buf = &self.buf[1..];
+        b
+    }
+
+    fn read_varint(&mut self) -> u64 {
+        let mut v = 0;
+        let mut shift = 0;
+        loop {
+            let b = self.read_byte();
+            v |= u64::from(b & 0x7f) << shift;
+            if b < 0x80 {
+                return v;
+            }
+            shift += 7;
+        }
+    }
+
+    fn read_binary(&mut self) -> Vec<u8> {
+        let len = self.read_varint() as usize;
+        let b = self.buf[..len].to_vec();
+        self.buf = &self.buf[len..];
+        b
+    }
+}
+
+#[cfg(test)]
+fn unzigzag(v: u64) -> i64 {
+    (v >> 1) as i64 ^ -((v & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varints() {
+        let mut w = CompactWriter::new();
+        w.write_i32(0);
+        w.write_i32(-1);
+        w.write_i32(1);
+        w.write_i32(-64);
+        w.write_i32(64);
+        w.write_i64(i64::MIN);
+        assert_eq!(
+            w.into_bytes(),
+            vec![0, 1, 2, 127, 0x80, 1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 1]
+        );
+    }
+
+    #[test]
+    fn message_begin() {
+        let mut w = CompactWriter::new();
+        w.write_message_begin("emitBatch", MESSAGE_ONEWAY, 1);
+        let mut want = vec![0x82, 0x81, 1, 9];
+        want.extend_from_slice(b"emitBatch");
+        assert_eq!(w.into_bytes(), want);
+    }
+
+    #[test]
+    fn field_headers() {
+        let mut w = CompactWriter::new();
+        w.write_struct_begin();
+        w.write_i32_field(1, 1);
+        w.write_bool_field(2, true);
+        w.write_bool_field(3, false);
+        w.write_i64_field(20, 1);
+        w.write_field_begin(TYPE_STRUCT, 21);
+        w.write_struct_begin();
+        w.write_i32_field(1, 1);
+        w.write_struct_end();
+        w.write_i32_field(22, 1);
+        w.write_struct_end();
+        assert_eq!(
+            w.into_bytes(),
+            vec![0x15, 2, 0x11, 0x12, 0x06, 40, 2, 0x1c, 0x15, 2, 0, 0x15, 2, 0]
+        );
+    }
+
+    #[test]
+    fn list_headers() {
+        let mut w = CompactWriter::new();
+        w.write_list_begin(TYPE_I32, 14);
+        w.write_list_begin(TYPE_STRUCT, 15);
+        w.write_list_begin(TYPE_BINARY, 300);
+        assert_eq!(w.into_bytes(), vec![0xe5, 0xfc, 15, 0xf8, 0xac, 2]);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut w = CompactWriter::new();
+        w.write_message_begin("call", MESSAGE_ONEWAY, 7);
+        w.write_struct_begin();
+        w.write_string_field(1, "name");
+        w.write_double_field(2, 1.5);
+        w.write_bool_field(3, true);
+        w.write_field_begin(TYPE_LIST, 40);
+        w.write_list_begin(TYPE_I64, 20);
+        for i in 0..20 {
+            w.write_i64(-i);
+        }
+        w.write_struct_end();
+        let b = w.into_bytes();
+
+        let mut r = CompactReader::new(&b);
+        assert_eq!(
+            r.read_message_begin(),
+            ("call".to_string(), MESSAGE_ONEWAY, 7)
+        );
+        let s = r.read_struct();
+        assert!(r.is_empty());
+        assert_eq!(s.string(1), "name");
+        assert_eq!(s.field(2), Some(&Value::Double(1.5)));
+        assert_eq!(s.field(3), Some(&Value::Bool(true)));
+        let want: Vec<_> = (0..20).map(|i| Value::Int(-i)).collect();
+        assert_eq!(s.list(40), &want[..]);
+    }
+}
@@ -0,0 +1,499 @@
+/*!
+Package ocagent contains an exporter for the OpenCensus agent.
+
+The exporter converts spans into opencensus-proto messages and streams them
+over gRPC to an OpenCensus agent, or an OpenTelemetry collector with the
+OpenCensus receiver enabled. Wrap it in a BatchExporter so that spans are
+sent in batches from a background thread:
+
+```no_run
+use std::sync::Arc;
+
+use opencensus_ocagent::{AgentConfig, AgentExporter};
+use opencensus_trace::{register_exporter, shutdown_exporters, BatchConfig, BatchExporter};
+
+let exporter = AgentExporter::new(AgentConfig {
+    service_name: "frontend".to_string(),
+    ..AgentConfig::default()
+})
+.unwrap();
+register_exporter(Arc::new(BatchExporter::new(
+    Arc::new(exporter),
+    BatchConfig::default(),
+)));
+
+// ...
+
+shutdown_exporters().unwrap();
+```
+
+The first request on every stream identifies the node, that is the host,
+process and service the spans come from, and the resource, if any. When the
+stream breaks, the exporter opens a new one on the next export, at most once
+per reconnection period. Spans sent just before the stream broke may be lost.
+
+The exporter runs its own tokio runtime, so it must not be used from within
+an async context.
+*/
+#![warn(missing_docs, rust_2018_idioms, clippy::all)]
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use opencensus_trace::{ExportError, SpanData, SpanExporter};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::codegen::http::uri::PathAndQuery;
+use tonic::transport::Endpoint;
+use tonic_prost::ProstCodec;
+
+use crate::proto::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+
+mod model;
+mod proto;
+
+/// DEFAULT_AGENT_ENDPOINT is the address of an OpenCensus agent running
+/// locally.
+pub const DEFAULT_AGENT_ENDPOINT: &str = "http://localhost:55678";
+/// DEFAULT_RECONNECTION_PERIOD is the default shortest time between attempts
+/// to connect to the agent.
+pub const DEFAULT_RECONNECTION_PERIOD: Duration = Duration::from_secs(5);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+// the number of requests queued on a stream before export blocks.
+const QUEUE_SIZE: usize = 16;
+
+/// Resource describes the entity the spans are recorded for, e.g. a container
+/// or a cloud instance.
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Resource {
+    /// resource_type is the type of the resource, e.g. "k8s.io/container".
+    pub resource_type: String,
+    /// labels describe the resource, e.g. its name or zone.
+    pub labels: HashMap<String, String>,
+}
+
+/// AgentConfig configures an AgentExporter.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct AgentConfig {
+    /// endpoint is the URI of the agent, e.g. DEFAULT_AGENT_ENDPOINT.
+    pub endpoint: String,
+    /// service_name is the name of the service recording the spans.
+    pub service_name: String,
+    /// resource is sent along with the node, if any.
+    pub resource: Option<Resource>,
+    /// reconnection_period is the shortest time between attempts to connect
+    /// to the agent.
+    pub reconnection_period: Duration,
+}
+
+impl Default for AgentConfig {
+    fn default() -> Self {
+        AgentConfig {
+            endpoint: DEFAULT_AGENT_ENDPOINT.to_string(),
+            service_name: String::new(),
+            resource: None,
+            reconnection_period: DEFAULT_RECONNECTION_PERIOD,
+        }
+    }
+}
+
+/// AgentExporter is a SpanExporter that streams spans to an OpenCensus agent.
+pub struct AgentExporter {
+    runtime: Runtime,
+    endpoint: Endpoint,
+    node: proto::Node,
+    resource: Option<proto::Resource>,
+    reconnection_period: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    stream: Option<Stream>,
+    last_attempt: Option<Instant>,
+    shut_down: bool,
+}
+
+// Stream is an open Export call, requests sent on sender are streamed to the
+// agent by task.
+struct Stream {
+    sender: mpsc::Sender<ExportTraceServiceRequest>,
+    task: JoinHandle<()>,
+}
+
+impl Stream {
+    fn is_open(&self) -> bool {
+        !self.task.is_finished() && !self.sender.is_closed()
+    }
+}
+
+impl AgentExporter {
+    /// new returns an exporter for the agent in config. It connects to the
+    /// agent when the first spans are exported.
+    pub fn new(config: AgentConfig) -> io::Result<Self> {
+        let endpoint = Endpoint::from_shared(config.endpoint)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+            .connect_timeout(CONNECT_TIMEOUT);
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("opencensus-ocagent")
+            .enable_all()
+            .build()?;
+
+        Ok(AgentExporter {
+            runtime,
+            endpoint,
+            node: node(&config.service_name),
+            resource: config.resource.map(|r| proto::Resource {
+                r#type: r.resource_type,
+                labels: r.labels,
+            }),
+            reconnection_period: config.reconnection_period,
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    fn connect(&self) -> Result<Stream, ExportError> {
+        let endpoint = self.endpoint.clone();
+        self.runtime.block_on(async move {
+            let channel = endpoint.connect().await.map_err(ExportError::other)?;
+            let mut client = tonic::client::Grpc::new(channel);
+            client.ready().await.map_err(ExportError::other)?;
+
+            let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+            let task = tokio::spawn(async move {
+                let request = tonic::Request::new(ReceiverStream::new(receiver));
+                let path = PathAndQuery::from_static(proto::EXPORT_PATH);
+                let codec =
+                    ProstCodec::<ExportTraceServiceRequest, ExportTraceServiceResponse>::default();
+                if let Ok(response) = client.streaming(request, path, codec).await {
+                    let mut responses = response.into_inner();
+                    while let Ok(Some(_)) = responses.message().await {}
+                }
+            });
+            Ok(Stream { sender, task })
+        })
+    }
+
+    // send returns the request back if the stream is closed.
+    fn send(
+        &self,
+        stream: &Stream,
+        request: ExportTraceServiceRequest,
+    ) -> Result<Result<(), ExportTraceServiceRequest>, ExportError> {
+        let sent = self
+            .runtime
+            .block_on(async { timeout(SEND_TIMEOUT, stream.sender.send(request)).await });
+        match sent {
+            Ok(Ok(())) => Ok(Ok(())),
+            Ok(Err(mpsc::error::SendError(request))) => Ok(Err(request)),
+            Err(_) => Err(ExportError::other("timed out sending spans to the agent")),
+        }
+    }
+}
+
+impl SpanExporter for AgentExporter {
+    fn export(&self, spans: &[SpanData]) -> Result<(), ExportError> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.shut_down {
+            return Err(ExportError::ShutDown);
+        }
+
+        let mut request = ExportTraceServiceRequest {
+            node: None,
+            spans: spans.iter().map(model::proto_span).collect(),
+            resource: None,
+        };
+        if let Some(stream) = state.stream.take().filter(Stream::is_open) {
+            let sent = self.send(&stream, request);
+            state.stream = Some(stream);
+            match sent? {
+                Ok(()) => return Ok(()),
+                Err(unsent) => request = unsent,
+            }
+        }
+
+        if let Some(last_attempt) = state.last_attempt {
+            if last_attempt.elapsed() < self.reconnection_period {
+                return Err(ExportError::other("not connected to the agent"));
+            }
+        }
+        state.last_attempt = Some(Instant::now());
+        let stream = self.connect()?;
+        request.node = Some(self.node.clone());
+        request.resource = self.resource.clone();
+        let sent = self.send(&stream, request);
+        state.stream = Some(stream);
+        match sent? {
+            Ok(()) => Ok(()),
+            Err(_) => Err(ExportError::other("the agent closed the stream")),
+        }
+    }
+
+    /// shutdown closes the stream, waiting for the requests queued on it to
+    /// be sent.
+    fn shutdown(&self) -> Result<(), ExportError> {
+        let mut state = self.state.lock().unwrap();
+        if state.shut_down {
+            return Err(ExportError::ShutDown);
+        }
+        state.shut_down = true;
+        if let Some(Stream { sender, task }) = state.stream.take() {
+            drop(sender);
+            let _ = self
+                .runtime
+                .block_on(async { timeout(SEND_TIMEOUT, task).await });
+        }
+        Ok(())
+    }
+}
+
+// node identifies this process, it is started at about the time the
+// exporter is created.
+fn node(service_name: &str) -> proto::Node {
+    proto::Node {
+        identifier: Some(proto::ProcessIdentifier {
+            host_name: gethostname::gethostname().to_string_lossy().into_owned(),
+            pid: std::process::id(),
+            start_timestamp: Some(model::timestamp(SystemTime::now())),
+        }),
+        library_info: Some(proto::LibraryInfo {
+            language: 0,
+            exporter_version: env!("CARGO_PKG_VERSION").to_string(),
+            core_library_version: String::new(),
+        }),
+        service_info: Some(proto::ServiceInfo {
+            name: service_name.to_string(),
+        }),
+        attributes: HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc as std_mpsc, Arc};
+    use std::thread;
+
+    use opencensus_trace::test_util::span_data;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::body::Body;
+    use tonic::codegen::{http, BoxFuture, BoxStream, Context, Poll, Service};
+    use tonic::server::Grpc;
+    use tonic::transport::Server;
+    use tonic::Streaming;
+
+    // MockAgent sends every request it receives, with the index of the stream
+    // it was received on, to requests. Streams are broken with an error after
+    // max_requests requests.
+    #[derive(Clone)]
+    struct MockAgent {
+        requests: std_mpsc::Sender<(usize, ExportTraceServiceRequest)>,
+        streams: Arc<AtomicUsize>,
+        max_requests: usize,
+    }
+
+    impl Service<http::Request<Body>> for MockAgent {
+        type Response = http::Response<Body>;
+        type Error = Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: http::Request<Body>) -> Self::Future {
+            assert_eq!(req.uri().path(), proto::EXPORT_PATH);
+            let handler = ExportHandler(self.clone());
+            Box::pin(async move {
+                let codec =
+                    ProstCodec::<ExportTraceServiceResponse, ExportTraceServiceRequest>::default();
+                Ok(Grpc::new(codec).streaming(handler, req).await)
+            })
+        }
+    }
+
+    struct ExportHandler(MockAgent);
+
+    impl Service<tonic::Request<Streaming<ExportTraceServiceRequest>>> for ExportHandler {
+        type Response = tonic::Response<BoxStream<ExportTraceServiceResponse>>;
+        type Error = tonic::Status;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(
+            &mut self,
+            req: tonic::Request<Streaming<ExportTraceServiceRequest>>,
+        ) -> Self::Future {
+            let agent = self.0.clone();
+            let stream = agent.streams.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                let mut requests = req.into_inner();
+                let mut received = 0;
+                while let Some(request) = requests.message().await? {
+                    agent.requests.send((stream, request)).unwrap();
+                    received += 1;
+                    if received == agent.max_requests {
+                        return Err(tonic::Status::unavailable("stream limit reached"));
+                    }
+                }
+                let responses: BoxStream<_> = Box::pin(tokio_stream::empty());
+                Ok(tonic::Response::new(responses))
+            })
+        }
+    }
+
+    // mock_agent starts a MockAgent and returns its endpoint, the runtime
+    // serving it and the requests it receives.
+    fn mock_agent(
+        max_requests: usize,
+    ) -> (
+        String,
+        Runtime,
+        std_mpsc::Receiver<(usize, ExportTraceServiceRequest)>,
+    ) {
+        let runtime = Runtime::new().unwrap();
+        let listener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = std_mpsc::channel();
+        let agent = MockAgent {
+            requests: sender,
+            streams: Arc::new(AtomicUsize::new(0)),
+            max_requests,
+        };
+        runtime
+            .spawn(Server::builder().serve_with_incoming(agent, TcpListenerStream::new(listener)));
+        (endpoint, runtime, receiver)
+    }
+
+    fn names(request: &ExportTraceServiceRequest) -> Vec<&str> {
+        request
+            .spans
+            .iter()
+            .map(|s| s.name.as_ref().unwrap().value.as_str())
+            .collect()
+    }
+
+    const LONG: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn streams_spans_with_node() {
+        let (endpoint, _agent, requests) = mock_agent(usize::MAX);
+        let exporter = AgentExporter::new(AgentConfig {
+            endpoint,
+            service_name: "frontend".to_string(),
+            resource: Some(Resource {
+                resource_type: "host".to_string(),
+                labels: vec![("zone".to_string(), "a".to_string())]
+                    .into_iter()
+                    .collect(),
+            }),
+            ..AgentConfig::default()
+        })
+        .unwrap();
+
+        exporter.export(&[span_data("a"), span_data("b")]).unwrap();
+        exporter.export(&[span_data("c")]).unwrap();
+        exporter.shutdown().unwrap();
+
+        let (stream, first) = requests.recv_timeout(LONG).unwrap();
+        assert_eq!(stream, 0);
+        assert_eq!(names(&first), vec!["a", "b"]);
+        let node = first.node.unwrap();
+        assert_eq!(node.service_info.unwrap().name, "frontend");
+        assert_eq!(node.identifier.unwrap().pid, std::process::id());
+        let resource = first.resource.unwrap();
+        assert_eq!(resource.r#type, "host");
+        assert_eq!(resource.labels["zone"], "a");
+
+        let (stream, second) = requests.recv_timeout(LONG).unwrap();
+        assert_eq!(stream, 0);
+        assert_eq!(names(&second), vec!["c"]);
+        assert_eq!(second.node, None);
+        assert_eq!(second.resource, None);
+
+        assert!(matches!(
+            exporter.export(&[span_data("d")]),
+            Err(ExportError::ShutDown)
+        ));
+        assert!(matches!(exporter.shutdown(), Err(ExportError::ShutDown)));
+    }
+
+    #[test]
+    fn reconnects_after_stream_breaks() {
+        let (endpoint, _agent, requests) = mock_agent(1);
+        let exporter = AgentExporter::new(AgentConfig {
+            endpoint,
+            reconnection_period: Duration::from_secs(0),
+            ..AgentConfig::default()
+        })
+        .unwrap();
+
+        exporter.export(&[span_data("a")]).unwrap();
+        let (stream, first) = requests.recv_timeout(LONG).unwrap();
+        assert_eq!(stream, 0);
+        assert!(first.node.is_some());
+
+        let deadline = Instant::now() + LONG;
+        while exporter
+            .state
+            .lock()
+            .unwrap()
+            .stream
+            .as_ref()
+            .is_some_and(Stream::is_open)
+        {
+            assert!(Instant::now() < deadline, "stream wasn't closed");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        exporter.export(&[span_data("b")]).unwrap();
+        let (stream, second) = requests.recv_timeout(LONG).unwrap();
+        assert_eq!(stream, 1);
+        assert_eq!(names(&second), vec!["b"]);
+        assert!(second.node.is_some());
+    }
+
+    #[test]
+    fn waits_to_reconnect() {
+        let exporter = AgentExporter::new(AgentConfig {
+            // nothing listens on port 1.
+            endpoint: "http://127.0.0.1:1".to_string(),
+            ..AgentConfig::default()
+        })
+        .unwrap();
+
+        assert!(exporter.export(&[span_data("a")]).is_err());
+        let err = exporter.export(&[span_data("b")]).unwrap_err();
+        assert_eq!(err.to_string(), "export failed: not connected to the agent");
+    }
+
+    #[test]
+    fn rejects_invalid_endpoints() {
+        let err = AgentExporter::new(AgentConfig {
+            endpoint: "not a uri".to_string(),
+            ..AgentConfig::default()
+        })
+        .err()
+        .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,391 @@
+use std::convert::TryFrom;
+use std::time::SystemTime;
+
+use opencensus_trace::{
+    AttributeValue, Attributes, LinkType, MessageEventType, SpanData, SpanKind,
+};
+use prost_types::Timestamp;
+
+use crate::proto::{self, attribute_value, time_event};
+
+// SPAN_KIND_ATTRIBUTE carries the kinds the OpenCensus protos have no value
+// for, the OpenTelemetry collector reads it when the kind is unspecified.
+const SPAN_KIND_ATTRIBUTE: &str = "span.kind";
+
+/// proto_span converts s into an OpenCensus proto span.
+pub fn proto_span(s: &SpanData) -> proto::Span {
+    let sc = &s.span_context;
+    let (kind, kind_attribute) = span_kind(&s.span_kind);
+    let mut attributes = proto_attributes(&s.attributes, s.dropped_attribute_count);
+    if let Some(kind) = kind_attribute {
+        attributes
+            .attribute_map
+            .insert(SPAN_KIND_ATTRIBUTE.to_string(), string_value(kind));
+    }
+
+    proto::Span {
+        trace_id: sc.trace_id.0.to_vec(),
+        span_id: sc.span_id.0.to_vec(),
+        tracestate: sc.trace_state.as_ref().map(|ts| proto::Tracestate {
+            entries: ts
+                .entries()
+                .map(|(key, value)| proto::TracestateEntry {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+        }),
+        parent_span_id: s.parent_span_id.map(|id| id.0.to_vec()).unwrap_or_default(),
+        name: Some(truncatable_string(&s.name)),
+        kind,
+        start_time: Some(timestamp(s.start_time)),
+        end_time: s.end_time.map(timestamp),
+        attributes: Some(attributes),
+        time_events: Some(time_events(s)),
+        links: Some(proto::Links {
+            link: s
+                .links
+                .iter()
+                .map(|link| proto::Link {
+                    trace_id: link.trace_id.0.to_vec(),
+                    span_id: link.span_id.0.to_vec(),
+                    r#type: link_type(&link._type),
+                    attributes: Some(proto_attributes(&link.attributes, 0)),
+                })
+                .collect(),
+            dropped_links_count: count(s.dropped_link_count),
+        }),
+        status: s.status.as_ref().map(|status| proto::Status {
+            code: i32::from(status.code.clone()),
+            message: status.message.clone(),
+        }),
+        same_process_as_parent_span: s.parent_span_id.map(|_| !s.has_remote_parent),
+    }
+}
+
+fn span_kind(kind: &SpanKind) -> (i32, Option<&'static str>) {
+    match kind {
+        SpanKind::Unspecified => (proto::SPAN_KIND_UNSPECIFIED, None),
+        SpanKind::Server => (proto::SPAN_KIND_SERVER, None),
+        SpanKind::Client => (proto::SPAN_KIND_CLIENT, None),
+        SpanKind::Producer => (proto::SPAN_KIND_UNSPECIFIED, Some("producer")),
+        SpanKind::Consumer => (proto::SPAN_KIND_UNSPECIFIED, Some("consumer")),
+        SpanKind::Internal => (proto::SPAN_KIND_UNSPECIFIED, Some("internal")),
+    }
+}
+
+fn link_type(t: &LinkType) -> i32 {
+    match t {
+        LinkType::Unspecified => proto::LINK_TYPE_UNSPECIFIED,
+        LinkType::Child => proto::LINK_CHILD_LINKED_SPAN,
+        LinkType::Parent => proto::LINK_PARENT_LINKED_SPAN,
+    }
+}
+
+// Annotations and message events are both time events, ordered by time.
+fn time_events(s: &SpanData) -> proto::TimeEvents {
+    let annotations = s.annotations.iter().map(|a| {
+        let annotation = time_event::Annotation {
+            description: Some(truncatable_string(&a.message)),
+            attributes: Some(proto_attributes(&a.attributes, 0)),
+        };
+        (a.time, time_event::Value::Annotation(annotation))
+    });
+    let events = s.message_events.iter().map(|e| {
+        let event = time_event::MessageEvent {
+            r#type: match e.event_type {
+                MessageEventType::Unspecified => time_event::MESSAGE_EVENT_TYPE_UNSPECIFIED,
+                MessageEventType::Sent => time_event::MESSAGE_EVENT_SENT,
+                MessageEventType::Recv => time_event::MESSAGE_EVENT_RECEIVED,
+            },
+            id: e.message_id as u64,
+            uncompressed_size: e.uncompressed_byte_size as u64,
+            compressed_size: e.compressed_byte_size as u64,
+        };
+        (e.time, time_event::Value::MessageEvent(event))
+    });
+    let mut time_events: Vec<_> = annotations.chain(events).collect();
+    time_events.sort_by_key(|(time, _)| *time);
+
+    proto::TimeEvents {
+        time_event: time_events
+            .into_iter()
+            .map(|(time, value)| proto::TimeEvent {
+                time: Some(timestamp(time)),
+                value: Some(value),
+            })
+            .collect(),
+        dropped_annotations_count: count(s.dropped_annotation_count),
+        dropped_message_events_count: count(s.dropped_message_event_count),
+    }
+}
+
+fn proto_attributes(attributes: &Attributes, dropped: usize) -> proto::Attributes {
+    proto::Attributes {
+        attribute_map: attributes
+            .iter()
+            .map(|(k, v)| (k.clone(), attribute_value(v)))
+            .collect(),
+        dropped_attributes_count: count(dropped),
+    }
+}
+
+// The protos only have string, int, bool and double values, so bytes are
+// encoded as base64 strings and arrays as JSON strings.
+fn attribute_value(v: &AttributeValue) -> proto::AttributeValue {
+    let value = match v {
+        AttributeValue::BoolAttribute(b) => attribute_value::Value::BoolValue(*b),
+        AttributeValue::Int64Attribute(i) => attribute_value::Value::IntValue(*i),
+        AttributeValue::DoubleAttribute(d) => attribute_value::Value::DoubleValue(*d),
+        AttributeValue::StringAttribute(s) => return string_value(s),
+        AttributeValue::BytesAttribute(_)
+        | AttributeValue::BoolArrayAttribute(_)
+        | AttributeValue::Int64ArrayAttribute(_)
+        | AttributeValue::DoubleArrayAttribute(_)
+        | AttributeValue::StringArrayAttribute(_) => return string_value(&v.to_string_value()),
+    };
+    proto::AttributeValue { value: Some(value) }
+}
+
+fn string_value(s: &str) -> proto::AttributeValue {
+    proto::AttributeValue {
+        value: Some(attribute_value::Value::StringValue(truncatable_string(s))),
+    }
+}
+
+fn truncatable_string(s: &str) -> proto::TruncatableString {
+    proto::TruncatableString {
+        value: s.to_string(),
+        truncated_byte_count: 0,
+    }
+}
+
+/// timestamp converts t into a protobuf Timestamp.
+pub fn timestamp(t: SystemTime) -> Timestamp {
+    Timestamp::from(t)
+}
+
+fn count(n: usize) -> i32 {
+    i32::try_from(n).unwrap_or(i32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use opencensus_trace::test_util::span_data;
+    use opencensus_trace::tracestate::{Key, Tracestate, Value};
+    use opencensus_trace::{
+        attributes, Annotation, Link, MessageEvent, SpanID, Status, StatusCode, TraceID,
+    };
+
+    fn at(micros: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(micros)
+    }
+
+    fn attribute(span: &proto::Span, key: &str) -> Option<attribute_value::Value> {
+        span.attributes
+            .as_ref()?
+            .attribute_map
+            .get(key)?
+            .value
+            .clone()
+    }
+
+    fn string(s: &str) -> Option<attribute_value::Value> {
+        Some(attribute_value::Value::StringValue(truncatable_string(s)))
+    }
+
+    #[test]
+    fn converts_span() {
+        let mut s = span_data("span");
+        s.span_context.trace_state = Some(
+            Tracestate::try_new(
+                None,
+                &[(
+                    Key::try_new("vendor").unwrap(),
+                    Value::try_new("value").unwrap(),
+                )],
+            )
+            .unwrap(),
+        );
+        s.parent_span_id = Some(SpanID([8, 7, 6, 5, 4, 3, 2, 1]));
+        s.has_remote_parent = true;
+        s.span_kind = SpanKind::Server;
+        s.attributes = attributes! {
+            "bool" => true,
+            "int" => 42,
+            "double" => 1.5,
+            "string" => "value",
+            "bytes" => vec![0u8, 1, 2],
+            "strings" => vec!["a", "b"],
+        };
+        s.dropped_attribute_count = 3;
+        s.annotations = vec![Annotation {
+            time: at(1_200),
+            message: "cache miss".to_string(),
+            attributes: attributes! { "key" => "k" },
+        }];
+        s.message_events = vec![MessageEvent {
+            time: at(1_100),
+            event_type: MessageEventType::Recv,
+            message_id: 1,
+            uncompressed_byte_size: 10,
+            compressed_byte_size: 5,
+        }];
+        s.dropped_annotation_count = 1;
+        s.links = vec![Link {
+            trace_id: TraceID([2; 16]),
+            span_id: SpanID([3; 8]),
+            _type: LinkType::Parent,
+            attributes: attributes! { "key" => "k" },
+        }];
+        s.dropped_link_count = 2;
+        s.status = Some(Status {
+            code: StatusCode::NotFound,
+            message: "no such user".to_string(),
+        });
+
+        let got = proto_span(&s);
+        assert_eq!(got.trace_id, (1..=16).collect::<Vec<u8>>());
+        assert_eq!(got.span_id, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(got.parent_span_id, vec![8, 7, 6, 5, 4, 3, 2, 1]);
+        assert_eq!(
+            got.tracestate.as_ref().unwrap().entries,
+            vec![proto::TracestateEntry {
+                key: "vendor".to_string(),
+                value: "value".to_string(),
+            }]
+        );
+        assert_eq!(got.name, Some(truncatable_string("span")));
+        assert_eq!(got.kind, proto::SPAN_KIND_SERVER);
+        assert_eq!(
+            got.start_time,
+            Some(Timestamp {
+                seconds: 0,
+                nanos: 1_000_000
+            })
+        );
+        assert_eq!(
+            got.end_time,
+            Some(Timestamp {
+                seconds: 0,
+                nanos: 1_500_000
+            })
+        );
+        assert_eq!(got.same_process_as_parent_span, Some(false));
+
+        assert_eq!(got.attributes.as_ref().unwrap().dropped_attributes_count, 3);
+        assert_eq!(
+            attribute(&got, "bool"),
+            Some(attribute_value::Value::BoolValue(true))
+        );
+        assert_eq!(
+            attribute(&got, "int"),
+            Some(attribute_value::Value::IntValue(42))
+        );
+        assert_eq!(
+            attribute(&got, "double"),
+            Some(attribute_value::Value::DoubleValue(1.5))
+        );
+        assert_eq!(attribute(&got, "string"), string("value"));
+        assert_eq!(attribute(&got, "bytes"), string("AAEC"));
+        assert_eq!(attribute(&got, "strings"), string("[\"a\",\"b\"]"));
+        assert_eq!(attribute(&got, SPAN_KIND_ATTRIBUTE), None);
+
+        let time_events = got.time_events.unwrap();
+        assert_eq!(time_events.dropped_annotations_count, 1);
+        assert_eq!(time_events.dropped_message_events_count, 0);
+        assert_eq!(
+            time_events.time_event,
+            vec![
+                proto::TimeEvent {
+                    time: Some(timestamp(at(1_100))),
+                    value: Some(time_event::Value::MessageEvent(time_event::MessageEvent {
+                        r#type: time_event::MESSAGE_EVENT_RECEIVED,
+                        id: 1,
+                        uncompressed_size: 10,
+                        compressed_size: 5,
+                    })),
+                },
+                proto::TimeEvent {
+                    time: Some(timestamp(at(1_200))),
+                    value: Some(time_event::Value::Annotation(time_event::Annotation {
+                        description: Some(truncatable_string("cache miss")),
+                        attributes: Some(proto::Attributes {
+                            attribute_map: vec![("key".to_string(), string_value("k"))]
+                                .into_iter()
+                                .collect(),
+                            dropped_attributes_count: 0,
+                        }),
+                    })),
+                },
+            ]
+        );
+
+        let links = got.links.unwrap();
+        assert_eq!(links.dropped_links_count, 2);
+        assert_eq!(links.link.len(), 1);
+        assert_eq!(links.link[0].trace_id, vec![2; 16]);
+        assert_eq!(links.link[0].span_id, vec![3; 8]);
+        assert_eq!(links.link[0].r#type, proto::LINK_PARENT_LINKED_SPAN);
+        assert_eq!(
+            links.link[0].attributes.as_ref().unwrap().attribute_map["key"],
+            string_value("k")
+        );
+
+        assert_eq!(
+            got.status,
+            Some(proto::Status {
+                code: 5,
+                message: "no such user".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn converts_root_span() {
+        let mut s = span_data("span");
+        s.end_time = None;
+
+        let got = proto_span(&s);
+        assert!(got.parent_span_id.is_empty());
+        assert_eq!(got.same_process_as_parent_span, None);
+        assert_eq!(got.tracestate, None);
+        assert_eq!(got.end_time, None);
+        assert_eq!(got.status, None);
+    }
+
+    #[test]
+    fn span_kinds() {
+        let kinds = [
+            (SpanKind::Unspecified, proto::SPAN_KIND_UNSPECIFIED, None),
+            (SpanKind::Server, proto::SPAN_KIND_SERVER, None),
+            (SpanKind::Client, proto::SPAN_KIND_CLIENT, None),
+            (
+                SpanKind::Producer,
+                proto::SPAN_KIND_UNSPECIFIED,
+                string("producer"),
+            ),
+            (
+                SpanKind::Consumer,
+                proto::SPAN_KIND_UNSPECIFIED,
+                string("consumer"),
+            ),
+            (
+                SpanKind::Internal,
+                proto::SPAN_KIND_UNSPECIFIED,
+                string("internal"),
+            ),
+        ];
+        for (kind, want_kind, want_attribute) in kinds.iter() {
+            let mut s = span_data("span");
+            s.span_kind = *kind;
+            let got = proto_span(&s);
+            assert_eq!(got.kind, *want_kind, "{:?}", kind);
+            assert_eq!(attribute(&got, SPAN_KIND_ATTRIBUTE), *want_attribute);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+// The messages of the OpenCensus agent trace service, written out by hand
+// from opencensus/proto/agent/trace/v1/trace_service.proto and its imports,
+// see https://github.com/census-instrumentation/opencensus-proto
+
+use std::collections::HashMap;
+
+use prost_types::Timestamp;
+
+/// EXPORT_PATH is the path of the TraceService.Export method.
+pub const EXPORT_PATH: &str = "/opencensus.proto.agent.trace.v1.TraceService/Export";
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportTraceServiceRequest {
+    #[prost(message, optional, tag = "1")]
+    pub node: Option<Node>,
+    #[prost(message, repeated, tag = "2")]
+    pub spans: Vec<Span>,
+    #[prost(message, optional, tag = "3")]
+    pub resource: Option<Resource>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ExportTraceServiceResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Node {
+    #[prost(message, optional, tag = "1")]
+    pub identifier: Option<ProcessIdentifier>,
+    #[prost(message, optional, tag = "2")]
+    pub library_info: Option<LibraryInfo>,
+    #[prost(message, optional, tag = "3")]
+    pub service_info: Option<ServiceInfo>,
+    #[prost(map = "string, string", tag = "4")]
+    pub attributes: HashMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ProcessIdentifier {
+    #[prost(string, tag = "1")]
+    pub host_name: String,
+    #[prost(uint32, tag = "2")]
+    pub pid: u32,
+    #[prost(message, optional, tag = "3")]
+    pub start_timestamp: Option<Timestamp>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct LibraryInfo {
+    // There is no value for Rust, so this is always LANGUAGE_UNSPECIFIED.
+    #[prost(int32, tag = "1")]
+    pub language: i32,
+    #[prost(string, tag = "2")]
+    pub exporter_version: String,
+    #[prost(string, tag = "3")]
+    pub core_library_version: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServiceInfo {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Resource {
+    #[prost(string, tag = "1")]
+    pub r#type: String,
+    #[prost(map = "string, string", tag = "2")]
+    pub labels: HashMap<String, String>,
+}
+
+pub const SPAN_KIND_UNSPECIFIED: i32 = 0;
+pub const SPAN_KIND_SERVER: i32 = 1;
+pub const SPAN_KIND_CLIENT: i32 = 2;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Span {
+    #[prost(bytes = "vec", tag = "1")]
+    pub trace_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub span_id: Vec<u8>,
+    #[prost(message, optional, tag = "15")]
+    pub tracestate: Option<Tracestate>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub parent_span_id: Vec<u8>,
+    #[prost(message, optional, tag = "4")]
+    pub name: Option<TruncatableString>,
+    #[prost(int32, tag = "14")]
+    pub kind: i32,
+    #[prost(message, optional, tag = "5")]
+    pub start_time: Option<Timestamp>,
+    #[prost(message, optional, tag = "6")]
+    pub end_time: Option<Timestamp>,
+    #[prost(message, optional, tag = "7")]
+    pub attributes: Option<Attributes>,
+    #[prost(message, optional, tag = "9")]
+    pub time_events: Option<TimeEvents>,
+    #[prost(message, optional, tag = "10")]
+    pub links: Option<Links>,
+    #[prost(message, optional, tag = "11")]
+    pub status: Option<Status>,
+    #[prost(message, optional, tag = "12")]
+    pub same_process_as_parent_span: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Tracestate {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<TracestateEntry>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TracestateEntry {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TruncatableString {
+    #[prost(string, tag = "1")]
+    pub value: String,
+    #[prost(int32, tag = "2")]
+    pub truncated_byte_count: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Attributes {
+    #[prost(map = "string, message", tag = "1")]
+    pub attribute_map: HashMap<String, AttributeValue>,
+    #[prost(int32, tag = "2")]
+    pub dropped_attributes_count: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct AttributeValue {
+    #[prost(oneof = "attribute_value::Value", tags = "1, 2, 3, 4")]
+    pub value: Option<attribute_value::Value>,
+}
+
+pub mod attribute_value {
+    // The variants are named after the fields of the oneof.
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(message, tag = "1")]
+        StringValue(super::TruncatableString),
+        #[prost(int64, tag = "2")]
+        IntValue(i64),
+        #[prost(bool, tag = "3")]
+        BoolValue(bool),
+        #[prost(double, tag = "4")]
+        DoubleValue(f64),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TimeEvent {
+    #[prost(message, optional, tag = "1")]
+    pub time: Option<Timestamp>,
+    #[prost(oneof = "time_event::Value", tags = "2, 3")]
+    pub value: Option<time_event::Value>,
+}
+
+pub mod time_event {
+    pub const MESSAGE_EVENT_TYPE_UNSPECIFIED: i32 = 0;
+    pub const MESSAGE_EVENT_SENT: i32 = 1;
+    pub const MESSAGE_EVENT_RECEIVED: i32 = 2;
+
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Value {
+        #[prost(message, tag = "2")]
+        Annotation(Annotation),
+        #[prost(message, tag = "3")]
+        MessageEvent(MessageEvent),
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Annotation {
+        #[prost(message, optional, tag = "1")]
+        pub description: Option<super::TruncatableString>,
+        #[prost(message, optional, tag = "2")]
+        pub attributes: Option<super::Attributes>,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MessageEvent {
+        #[prost(int32, tag = "1")]
+        pub r#type: i32,
+        #[prost(uint64, tag = "2")]
+        pub id: u64,
+        #[prost(uint64, tag = "3")]
+        pub uncompressed_size: u64,
+        #[prost(uint64, tag = "4")]
+        pub compressed_size: u64,
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TimeEvents {
+    #[prost(message, repeated, tag = "1")]
+    pub time_event: Vec<TimeEvent>,
+    #[prost(int32, tag = "2")]
+    pub dropped_annotations_count: i32,
+    #[prost(int32, tag = "3")]
+    pub dropped_message_events_count: i32,
+}
+
+pub const LINK_TYPE_UNSPECIFIED: i32 = 0;
+pub const LINK_CHILD_LINKED_SPAN: i32 = 1;
+pub const LINK_PARENT_LINKED_SPAN: i32 = 2;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Link {
+    #[prost(bytes = "vec", tag = "1")]
+    pub trace_id: Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub span_id: Vec<u8>,
+    #[prost(int32, tag = "3")]
+    pub r#type: i32,
+    #[prost(message, optional, tag = "4")]
+    pub attributes: Option<Attributes>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Links {
+    #[prost(message, repeated, tag = "1")]
+    pub link: Vec<Link>,
+    #[prost(int32, tag = "2")]
+    pub dropped_links_count: i32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Status {
+    #[prost(int32, tag = "1")]
+    pub code: i32,
+    #[prost(string, tag = "2")]
+    pub message: String,
+}
@@ -0,0 +1,188 @@
+/*!
+Package zipkin contains an exporter for Zipkin.
+
+The exporter converts spans into the Zipkin v2 JSON model and reports them to
+a Zipkin collector over HTTP. Wrap it in a BatchExporter so that spans are
+reported in batches from a background thread:
+
+```no_run
+use std::sync::Arc;
+
+use opencensus_trace::{register_exporter, shutdown_exporters, BatchConfig, BatchExporter};
+use opencensus_zipkin::{Endpoint, ZipkinExporter, DEFAULT_ENDPOINT};
+
+let exporter = ZipkinExporter::new(DEFAULT_ENDPOINT, Endpoint::new("frontend"));
+register_exporter(Arc::new(BatchExporter::new(
+    Arc::new(exporter),
+    BatchConfig::default(),
+)));
+
+// ...
+
+shutdown_exporters().unwrap();
+```
+
+The span kind maps to the Zipkin kind, annotations and message events to
+Zipkin annotations, and attributes to tags. A status that isn't OK sets the
+"error" tag to the name of its code. The remote endpoint is taken from the
+peer.service, peer.ipv4, peer.ipv6 and peer.port attributes.
+
+HTTPS collectors need the "tls" feature.
+*/
+#![warn(missing_docs, rust_2018_idioms, clippy::all)]
+
+use std::time::Duration;
+
+use opencensus_trace::{ExportError, SpanData, SpanExporter};
+
+mod model;
+
+pub use crate::model::{
+    Endpoint, PEER_IPV4_ATTRIBUTE, PEER_IPV6_ATTRIBUTE, PEER_PORT_ATTRIBUTE, PEER_SERVICE_ATTRIBUTE,
+};
+
+/// DEFAULT_ENDPOINT is the span endpoint of a Zipkin collector running
+/// locally.
+pub const DEFAULT_ENDPOINT: &str = "http://localhost:9411/api/v2/spans";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// ZipkinExporter is a SpanExporter that reports spans to a Zipkin collector.
+pub struct ZipkinExporter {
+    agent: ureq::Agent,
+    url: String,
+    local_endpoint: Endpoint,
+}
+
+impl ZipkinExporter {
+    /// new returns an exporter that POSTs spans to the Zipkin v2 span endpoint
+    /// at url, e.g. DEFAULT_ENDPOINT. The spans are recorded as coming from
+    /// local_endpoint.
+    pub fn new(url: &str, local_endpoint: Endpoint) -> Self {
+        ZipkinExporter {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            url: url.to_string(),
+            local_endpoint,
+        }
+    }
+}
+
+impl SpanExporter for ZipkinExporter {
+    fn export(&self, spans: &[SpanData]) -> Result<(), ExportError> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+        let spans: Vec<_> = spans
+            .iter()
+            .map(|s| model::zipkin_span(s, &self.local_endpoint))
+            .collect();
+        let body = serde_json::to_vec(&spans).map_err(ExportError::other)?;
+
+        self.agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_bytes(&body)
+            .map_err(ExportError::other)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    use opencensus_trace::test_util::span_data;
+    use opencensus_trace::SpanKind;
+
+    struct Request {
+        request_line: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    // mock_collector accepts a single request, answers it with status and
+    // sends it back over the returned channel.
+    fn mock_collector(status: &'static str) -> (String, mpsc::Receiver<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/v2/spans", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut headers = HashMap::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(": ") {
+                    Some((name, value)) => {
+                        headers.insert(name.to_lowercase(), value.to_string());
+                    }
+                    None => break,
+                }
+            }
+            let len = headers["content-length"].parse().unwrap();
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            )
+            .unwrap();
+            sender
+                .send(Request {
+                    request_line: request_line.trim_end().to_string(),
+                    headers,
+                    body,
+                })
+                .unwrap();
+        });
+        (url, receiver)
+    }
+
+    #[test]
+    fn posts_spans_to_collector() {
+        let (url, requests) = mock_collector("202 Accepted");
+        let exporter = ZipkinExporter::new(&url, Endpoint::new("frontend"));
+
+        let mut a = span_data("a");
+        a.span_kind = SpanKind::Server;
+        exporter.export(&[a, span_data("b")]).unwrap();
+
+        let req = requests.recv().unwrap();
+        assert_eq!(req.request_line, "POST /api/v2/spans HTTP/1.1");
+        assert_eq!(req.headers["content-type"], "application/json");
+        let spans: Vec<serde_json::Value> = serde_json::from_slice(&req.body).unwrap();
+        let names: Vec<_> = spans.iter().map(|s| &s["name"]).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(spans[0]["kind"], "SERVER");
+        assert_eq!(spans[0]["duration"], 500);
+        assert_eq!(spans[0]["localEndpoint"]["serviceName"], "frontend");
+    }
+
+    #[test]
+    fn reports_collector_errors() {
+        let (url, requests) = mock_collector("500 Internal Server Error");
+        let exporter = ZipkinExporter::new(&url, Endpoint::new("frontend"));
+
+        let err = exporter.export(&[span_data("a")]).unwrap_err();
+        assert!(err.to_string().contains("500"), "{}", err);
+        requests.recv().unwrap();
+    }
+
+    #[test]
+    fn skips_empty_batches() {
+        let exporter = ZipkinExporter::new("http://127.0.0.1:1/api/v2/spans", Endpoint::default());
+        exporter.export(&[]).unwrap();
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use opencensus_trace::{
+    epoch_micros, AttributeValue, Attributes, MessageEventType, SpanData, SpanKind, StatusCode,
+};
+use serde::Serialize;
+
+/// PEER_SERVICE_ATTRIBUTE is the attribute key for the service name of the
+/// remote endpoint.
+pub const PEER_SERVICE_ATTRIBUTE: &str = "peer.service";
+/// PEER_IPV4_ATTRIBUTE is the attribute key for the IPv4 address of the
+/// remote endpoint.
+pub const PEER_IPV4_ATTRIBUTE: &str = "peer.ipv4";
+/// PEER_IPV6_ATTRIBUTE is the attribute key for the IPv6 address of the
+/// remote endpoint.
+pub const PEER_IPV6_ATTRIBUTE: &str = "peer.ipv6";
+/// PEER_PORT_ATTRIBUTE is the attribute key for the port of the remote
+/// endpoint.
+pub const PEER_PORT_ATTRIBUTE: &str = "peer.port";
+
+const STATUS_CODE_TAG: &str = "error";
+const STATUS_DESCRIPTION_TAG: &str = "opencensus.status_description";
+
+/// Endpoint is the network context of a service recording or receiving spans.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Endpoint {
+    /// service_name is the lowercase name of the service, e.g. "backend".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_name: Option<String>,
+    /// ipv4 is the IPv4 address of the service.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv4: Option<Ipv4Addr>,
+    /// ipv6 is the IPv6 address of the service.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipv6: Option<Ipv6Addr>,
+    /// port is the port the service listens on, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+}
+
+impl Endpoint {
+    /// new returns an Endpoint for the named service.
+    pub fn new(service_name: &str) -> Self {
+        Endpoint {
+            service_name: Some(service_name.to_string()),
+            ..Endpoint::default()
+        }
+    }
+
+    /// with_addr returns the Endpoint with its address and port set to addr.
+    pub fn with_addr(mut self, addr: SocketAddr) -> Self {
+        match addr.ip() {
+            IpAddr::V4(ip) => self.ipv4 = Some(ip),
+            IpAddr::V6(ip) => self.ipv6 = Some(ip),
+        }
+        self.port = Some(addr.port());
+        self
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Kind {
+    Client,
+    Server,
+    Producer,
+    Consumer,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Serialize)]
+pub struct Annotation {
+    pub timestamp: u64,
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Span {
+    pub trace_id: String,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<Kind>,
+    pub timestamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u64>,
+    pub local_endpoint: Endpoint,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_endpoint: Option<Endpoint>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Annotation>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+/// zipkin_span converts s into the Zipkin v2 model, recorded by local.
+pub fn zipkin_span(s: &SpanData, local: &Endpoint) -> Span {
+    let timestamp = epoch_micros(s.start_time);
+    // Zipkin treats a zero duration as missing, so round short spans up.
+    let duration = s
+        .end_time
+        .map(|end| epoch_micros(end).saturating_sub(timestamp).max(1));
+
+    let mut attributes = s.attributes.clone();
+    let remote_endpoint = remote_endpoint(&mut attributes);
+
+    let mut tags: BTreeMap<_, _> = attributes
+        .into_iter()
+        .map(|(k, v)| (k, v.to_string_value()))
+        .collect();
+    if let Some(status) = &s.status {
+        if status.code != StatusCode::OK {
+            tags.insert(STATUS_CODE_TAG.to_string(), status.code.to_string());
+        }
+        if !status.message.is_empty() {
+            tags.insert(STATUS_DESCRIPTION_TAG.to_string(), status.message.clone());
+        }
+    }
+
+    Span {
+        trace_id: s.span_context.trace_id.to_string(),
+        id: s.span_context.span_id.to_string(),
+        parent_id: s.parent_span_id.map(|id| id.to_string()),
+        name: s.name.clone(),
+        kind: kind(&s.span_kind),
+        timestamp,
+        duration,
+        local_endpoint: local.clone(),
+        remote_endpoint,
+        annotations: annotations(s),
+        tags,
+    }
+}
+
+fn kind(kind: &SpanKind) -> Option<Kind> {
+    match kind {
+        SpanKind::Client => Some(Kind::Client),
+        SpanKind::Server => Some(Kind::Server),
+        SpanKind::Producer => Some(Kind::Producer),
+        SpanKind::Consumer => Some(Kind::Consumer),
+        SpanKind::Unspecified | SpanKind::Internal => None,
+    }
+}
+
+// The attributes of annotations have no counterpart in the Zipkin model and
+// are dropped.
+fn annotations(s: &SpanData) -> Vec<Annotation> {
+    let messages = s.annotations.iter().map(|a| (a.time, a.message.clone()));
+    let events = s.message_events.iter().filter_map(|e| match e.event_type {
+        MessageEventType::Sent => Some((e.time, "SENT".to_string())),
+        MessageEventType::Recv => Some((e.time, "RECV".to_string())),
+        MessageEventType::Unspecified => None,
+    });
+
+    let mut annotations: Vec<_> = messages
+        .chain(events)
+        .map(|(time, value)| Annotation {
+            timestamp: epoch_micros(time),
+            value,
+        })
+        .collect();
+    annotations.sort_by_key(|a| a.timestamp);
+    annotations
+}
+
+// remote_endpoint removes the peer attributes and returns the endpoint they
+// describe, if any.
+fn remote_endpoint(attributes: &mut Attributes) -> Option<Endpoint> {
+    let endpoint = Endpoint {
+        service_name: match attributes.remove(PEER_SERVICE_ATTRIBUTE) {
+            Some(AttributeValue::StringAttribute(name)) => Some(name),
+            _ => None,
+        },
+        ipv4: match attributes.remove(PEER_IPV4_ATTRIBUTE) {
+            Some(AttributeValue::StringAttribute(ip)) => ip.parse().ok(),
+            _ => None,
+        },
+        ipv6: match attributes.remove(PEER_IPV6_ATTRIBUTE) {
+            Some(AttributeValue::StringAttribute(ip)) => ip.parse().ok(),
+            _ => None,
+        },
+        port: match attributes.remove(PEER_PORT_ATTRIBUTE) {
+            Some(AttributeValue::Int64Attribute(port)) => u16::try_from(port).ok(),
+            Some(AttributeValue::StringAttribute(port)) => port.parse().ok(),
+            _ => None,
+        },
+    };
+    if endpoint == Endpoint::default() {
+        None
+    } else {
+        Some(endpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use opencensus_trace::test_util::span_data;
+    use opencensus_trace::{
+        attributes, Annotation as SpanAnnotation, MessageEvent, SpanID, Status,
+    };
+    use serde_json::json;
+
+    fn at(micros: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(micros)
+    }
+
+    #[test]
+    fn converts_span() {
+        let mut s = span_data("span");
+        s.parent_span_id = Some(SpanID([8, 7, 6, 5, 4, 3, 2, 1]));
+        s.span_kind = SpanKind::Client;
+        s.attributes = attributes! {
+            "bool" => true,
+            "int" => 42,
+            "string" => "value",
+            "double" => 1.5,
+            "bytes" => vec![0u8, 1, 2],
+            "ints" => vec![1i64, 2],
+            "strings" => vec!["a", "b"],
+            "peer.service" => "backend",
+            "peer.ipv4" => "10.0.0.1",
+            "peer.port" => 8080,
+        };
+        s.annotations = vec![SpanAnnotation {
+            time: at(1_200),
+            message: "cache miss".to_string(),
+            attributes: attributes! { "ignored" => true },
+        }];
+        s.message_events = vec![
+            MessageEvent {
+                time: at(1_100),
+                event_type: MessageEventType::Sent,
+                message_id: 1,
+                uncompressed_byte_size: 10,
+                compressed_byte_size: 5,
+            },
+            MessageEvent {
+                time: at(1_300),
+                event_type: MessageEventType::Recv,
+                message_id: 1,
+                uncompressed_byte_size: 10,
+                compressed_byte_size: 5,
+            },
+        ];
+        s.status = Some(Status {
+            code: StatusCode::NotFound,
+            message: "no such user".to_string(),
+        });
+
+        let local = Endpoint::new("frontend").with_addr("127.0.0.1:80".parse().unwrap());
+        let got = serde_json::to_value(zipkin_span(&s, &local)).unwrap();
+        assert_eq!(
+            got,
+            json!({
+                "traceId": "0102030405060708090a0b0c0d0e0f10",
+                "id": "0102030405060708",
+                "parentId": "0807060504030201",
+                "name": "span",
+                "kind": "CLIENT",
+                "timestamp": 1_000,
+                "duration": 500,
+                "localEndpoint": {
+                    "serviceName": "frontend",
+                    "ipv4": "127.0.0.1",
+                    "port": 80,
+                },
+                "remoteEndpoint": {
+                    "serviceName": "backend",
+                    "ipv4": "10.0.0.1",
+                    "port": 8080,
+                },
+                "annotations": [
+                    {"timestamp": 1_100, "value": "SENT"},
+                    {"timestamp": 1_200, "value": "cache miss"},
+                    {"timestamp": 1_300, "value": "RECV"},
+                ],
+                "tags": {
+                    "bool": "true",
+                    "int": "42",
+                    "string": "value",
+                    "double": "1.5",
+                    "bytes": "AAEC",
+                    "ints": "[1,2]",
+                    "strings": "[\"a\",\"b\"]",
+                    "error": "NOT_FOUND",
+                    "opencensus.status_description": "no such user",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn converts_minimal_span() {
+        let mut s = span_data("span");
+        s.end_time = Some(s.start_time);
+        s.status = Some(Status::default());
+
+        let got = serde_json::to_value(zipkin_span(&s, &Endpoint::default())).unwrap();
+        assert_eq!(
+            got,
+            json!({
+                "traceId": "0102030405060708090a0b0c0d0e0f10",
+                "id": "0102030405060708",
+                "name": "span",
+                "timestamp": 1_000,
+                "duration": 1,
+                "localEndpoint": {},
+            })
+        );
+    }
+
+    #[test]
+    fn span_kinds() {
+        let kinds = [
+            (SpanKind::Unspecified, None),
+            (SpanKind::Server, Some(Kind::Server)),
+            (SpanKind::Client, Some(Kind::Client)),
+            (SpanKind::Producer, Some(Kind::Producer)),
+            (SpanKind::Consumer, Some(Kind::Consumer)),
+            (SpanKind::Internal, None),
+        ];
+        for (span_kind, want) in kinds.iter() {
+            assert_eq!(kind(span_kind), *want, "{:?}", span_kind);
+        }
+    }
+}
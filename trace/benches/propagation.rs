@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 
+use opencensus_trace::propagation::trace_context::{from_traceparent, to_traceparent};
 use opencensus_trace::propagation::{from_binary, to_binary};
 use opencensus_trace::{SpanContext, SpanID, TraceID, TraceOptions};
 
@@ -32,11 +33,48 @@ fn benchmark_from_binary(c: &mut Criterion) {
 
     c.bench_function("from_binary", move |b| {
         b.iter(|| {
-            from_binary(&bin);
+            from_binary(&bin).unwrap();
         })
     });
 }
 
-criterion_group!(benches, benchmark_to_binary, benchmark_from_binary);
+fn benchmark_to_traceparent(c: &mut Criterion) {
+    let trace_id = TraceID([
+        0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x4b, 0x4c, 0x4d, 0x4e,
+        0x4f,
+    ]);
+    let span_id = SpanID([0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68]);
+    let trace_options = TraceOptions(1);
+    let span_context = SpanContext {
+        trace_id,
+        span_id,
+        trace_options,
+        trace_state: None,
+    };
+
+    c.bench_function("to_traceparent", move |b| {
+        b.iter(|| {
+            to_traceparent(&span_context);
+        })
+    });
+}
+
+fn benchmark_from_traceparent(c: &mut Criterion) {
+    let value = "00-404142434445464748494a4b4c4d4e4f-6162636465666768-01";
+
+    c.bench_function("from_traceparent", move |b| {
+        b.iter(|| {
+            from_traceparent(value);
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    benchmark_to_binary,
+    benchmark_from_binary,
+    benchmark_to_traceparent,
+    benchmark_from_traceparent
+);
 
 criterion_main!(benches);
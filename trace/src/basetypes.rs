@@ -1,5 +1,10 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
 use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
 use std::time;
 
 use crate::status_codes::StatusCode;
@@ -36,7 +41,7 @@ impl fmt::Display for SpanID {
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct Annotation {
     /// time is the time when the annotation was added.
-    pub time: time::Instant,
+    pub time: time::SystemTime,
     /// message is the message of the annotation.
     pub message: String,
     /// attributes is a mapping of attributes for the annotation.
@@ -47,8 +52,42 @@ pub struct Annotation {
 //TODO(john|p=4|#techdebt): consider using a newtype if not too clunky.
 pub type Attributes = HashMap<String, AttributeValue>;
 
+/// attributes! builds Attributes from key => value pairs. Keys are converted
+/// with String::from and values with AttributeValue::from, so the types are
+/// checked at compile time.
+///
+/// ```
+/// use opencensus_trace::{attributes, AttributeValue};
+///
+/// let attrs = attributes! {
+///     "http.method" => "GET",
+///     "http.status_code" => 200,
+///     "cache.hit" => true,
+///     "score" => 0.75,
+/// };
+/// assert_eq!(attrs["http.status_code"], AttributeValue::Int64Attribute(200));
+/// ```
+#[macro_export]
+macro_rules! attributes {
+    ($($key:expr => $value:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut attributes = $crate::Attributes::new();
+        $(
+            attributes.insert(
+                ::std::string::String::from($key),
+                $crate::AttributeValue::from($value),
+            );
+        )*
+        attributes
+    }};
+}
+
 /// AttributeValues are the values of attributes on a span, link or annotation.
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+///
+/// Doubles are compared and hashed by their bit patterns, as by f64::total_cmp,
+/// so that AttributeValue can be Eq and Hash: NaN is equal to itself, while
+/// 0.0 and -0.0 are different values.
+#[derive(Clone, Debug)]
 pub enum AttributeValue {
     /// BoolAttribute is an attribute of type bool.
     BoolAttribute(bool),
@@ -56,6 +95,233 @@ pub enum AttributeValue {
     Int64Attribute(i64),
     /// StringAttribute is an attribute of type String.
     StringAttribute(String),
+    /// DoubleAttribute is an attribute of type f64.
+    DoubleAttribute(f64),
+    /// BytesAttribute is an attribute holding an array of bytes.
+    BytesAttribute(Vec<u8>),
+    /// BoolArrayAttribute is an attribute holding an array of bools.
+    BoolArrayAttribute(Vec<bool>),
+    /// Int64ArrayAttribute is an attribute holding an array of i64s.
+    Int64ArrayAttribute(Vec<i64>),
+    /// DoubleArrayAttribute is an attribute holding an array of f64s.
+    DoubleArrayAttribute(Vec<f64>),
+    /// StringArrayAttribute is an attribute holding an array of Strings.
+    StringArrayAttribute(Vec<String>),
+}
+
+impl AttributeValue {
+    /// to_string_value returns the value as a string, for exporters whose
+    /// backend lacks some of the attribute types. Bytes are encoded as base64
+    /// and arrays as JSON.
+    #[cfg(feature = "string-attributes")]
+    pub fn to_string_value(&self) -> String {
+        use base64::Engine;
+
+        match self {
+            AttributeValue::BoolAttribute(b) => b.to_string(),
+            AttributeValue::Int64Attribute(i) => i.to_string(),
+            AttributeValue::StringAttribute(s) => s.clone(),
+            AttributeValue::DoubleAttribute(d) => d.to_string(),
+            AttributeValue::BytesAttribute(b) => {
+                base64::engine::general_purpose::STANDARD.encode(b)
+            }
+            AttributeValue::BoolArrayAttribute(a) => {
+                serde_json::Value::from(a.as_slice()).to_string()
+            }
+            AttributeValue::Int64ArrayAttribute(a) => {
+                serde_json::Value::from(a.as_slice()).to_string()
+            }
+            AttributeValue::DoubleArrayAttribute(a) => {
+                serde_json::Value::from(a.as_slice()).to_string()
+            }
+            AttributeValue::StringArrayAttribute(a) => {
+                serde_json::Value::from(a.as_slice()).to_string()
+            }
+        }
+    }
+
+    fn variant_index(&self) -> u8 {
+        match self {
+            AttributeValue::BoolAttribute(_) => 0,
+            AttributeValue::Int64Attribute(_) => 1,
+            AttributeValue::StringAttribute(_) => 2,
+            AttributeValue::DoubleAttribute(_) => 3,
+            AttributeValue::BytesAttribute(_) => 4,
+            AttributeValue::BoolArrayAttribute(_) => 5,
+            AttributeValue::Int64ArrayAttribute(_) => 6,
+            AttributeValue::DoubleArrayAttribute(_) => 7,
+            AttributeValue::StringArrayAttribute(_) => 8,
+        }
+    }
+}
+
+impl PartialEq for AttributeValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for AttributeValue {}
+
+impl PartialOrd for AttributeValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for AttributeValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use AttributeValue::*;
+        match (self, other) {
+            (BoolAttribute(a), BoolAttribute(b)) => a.cmp(b),
+            (Int64Attribute(a), Int64Attribute(b)) => a.cmp(b),
+            (StringAttribute(a), StringAttribute(b)) => a.cmp(b),
+            (DoubleAttribute(a), DoubleAttribute(b)) => a.total_cmp(b),
+            (BytesAttribute(a), BytesAttribute(b)) => a.cmp(b),
+            (BoolArrayAttribute(a), BoolArrayAttribute(b)) => a.cmp(b),
+            (Int64ArrayAttribute(a), Int64ArrayAttribute(b)) => a.cmp(b),
+            (DoubleArrayAttribute(a), DoubleArrayAttribute(b)) => a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| a.total_cmp(b))
+                .find(|o| *o != Ordering::Equal)
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (StringArrayAttribute(a), StringArrayAttribute(b)) => a.cmp(b),
+            _ => self.variant_index().cmp(&other.variant_index()),
+        }
+    }
+}
+
+impl Hash for AttributeValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.variant_index().hash(state);
+        match self {
+            AttributeValue::BoolAttribute(v) => v.hash(state),
+            AttributeValue::Int64Attribute(v) => v.hash(state),
+            AttributeValue::StringAttribute(v) => v.hash(state),
+            AttributeValue::DoubleAttribute(v) => v.to_bits().hash(state),
+            AttributeValue::BytesAttribute(v) => v.hash(state),
+            AttributeValue::BoolArrayAttribute(v) => v.hash(state),
+            AttributeValue::Int64ArrayAttribute(v) => v.hash(state),
+            AttributeValue::DoubleArrayAttribute(v) => {
+                v.len().hash(state);
+                for d in v {
+                    d.to_bits().hash(state);
+                }
+            }
+            AttributeValue::StringArrayAttribute(v) => v.hash(state),
+        }
+    }
+}
+
+impl From<bool> for AttributeValue {
+    fn from(v: bool) -> Self {
+        AttributeValue::BoolAttribute(v)
+    }
+}
+
+impl From<i64> for AttributeValue {
+    fn from(v: i64) -> Self {
+        AttributeValue::Int64Attribute(v)
+    }
+}
+
+macro_rules! impl_from_int {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for AttributeValue {
+                fn from(v: $t) -> Self {
+                    AttributeValue::Int64Attribute(i64::from(v))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_int!(i8, i16, i32, u8, u16, u32);
+
+// Wider integers may not fit in an Int64Attribute, so they only convert with
+// TryFrom.
+macro_rules! impl_try_from_int {
+    ($($t:ty),*) => {
+        $(
+            impl TryFrom<$t> for AttributeValue {
+                type Error = std::num::TryFromIntError;
+
+                fn try_from(v: $t) -> Result<Self, Self::Error> {
+                    i64::try_from(v).map(AttributeValue::Int64Attribute)
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_int!(isize, usize, u64, i128, u128);
+
+impl From<String> for AttributeValue {
+    fn from(v: String) -> Self {
+        AttributeValue::StringAttribute(v)
+    }
+}
+
+impl From<&str> for AttributeValue {
+    fn from(v: &str) -> Self {
+        AttributeValue::StringAttribute(v.to_string())
+    }
+}
+
+impl From<f64> for AttributeValue {
+    fn from(v: f64) -> Self {
+        AttributeValue::DoubleAttribute(v)
+    }
+}
+
+impl From<f32> for AttributeValue {
+    fn from(v: f32) -> Self {
+        AttributeValue::DoubleAttribute(f64::from(v))
+    }
+}
+
+impl From<Vec<u8>> for AttributeValue {
+    fn from(v: Vec<u8>) -> Self {
+        AttributeValue::BytesAttribute(v)
+    }
+}
+
+impl From<&[u8]> for AttributeValue {
+    fn from(v: &[u8]) -> Self {
+        AttributeValue::BytesAttribute(v.to_vec())
+    }
+}
+
+impl From<Vec<bool>> for AttributeValue {
+    fn from(v: Vec<bool>) -> Self {
+        AttributeValue::BoolArrayAttribute(v)
+    }
+}
+
+impl From<Vec<i64>> for AttributeValue {
+    fn from(v: Vec<i64>) -> Self {
+        AttributeValue::Int64ArrayAttribute(v)
+    }
+}
+
+impl From<Vec<f64>> for AttributeValue {
+    fn from(v: Vec<f64>) -> Self {
+        AttributeValue::DoubleArrayAttribute(v)
+    }
+}
+
+impl From<Vec<String>> for AttributeValue {
+    fn from(v: Vec<String>) -> Self {
+        AttributeValue::StringArrayAttribute(v)
+    }
+}
+
+impl From<Vec<&str>> for AttributeValue {
+    fn from(v: Vec<&str>) -> Self {
+        AttributeValue::StringArrayAttribute(v.into_iter().map(String::from).collect())
+    }
 }
 
 /// LinkType specifies the relationship between the span that had the link
@@ -66,20 +332,24 @@ pub enum LinkType {
     Unspecified = 0,
     /// The current span is a child of the linked span.
     Child,
-    /// The current span is a child of the linked span.
+    /// The current span is the parent of the linked span.
     Parent,
 }
 
 /// Link represents a reference from one span to another span.
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct Link {
+    /// trace_id is the trace ID of the linked span.
     pub trace_id: TraceID,
+    /// span_id is the span ID of the linked span.
     pub span_id: SpanID,
+    /// _type is the relationship of the current span to the linked span.
     pub _type: LinkType,
+    /// attributes is a mapping of attributes for the link.
     pub attributes: Attributes,
 }
 
-/// The current span is a child of the linked span.
+/// MessageEventType specifies whether a message was sent or received.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub enum MessageEventType {
     /// Unknown event type.
@@ -93,16 +363,287 @@ pub enum MessageEventType {
 /// MessageEvent represents an event describing a message sent or received on the network.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub struct MessageEvent {
-    pub time: time::Instant,
+    /// time is the time when the message was sent or received.
+    pub time: time::SystemTime,
+    /// event_type is whether the message was sent or received.
     pub event_type: MessageEventType,
+    /// message_id identifies the message within the span.
     pub message_id: i64,
+    /// uncompressed_byte_size is the size of the message before compression.
     pub uncompressed_byte_size: i64,
+    /// compressed_byte_size is the size of the message after compression.
     pub compressed_byte_size: i64,
 }
 
 /// Status is the status of a Span.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
 pub struct Status {
+    /// code is the canonical status code of the span.
     pub code: StatusCode,
+    /// message is a developer-facing description of the status.
     pub message: String,
 }
+
+impl Status {
+    /// from_error returns the status for an error.
+    ///
+    /// The message joins the error and its chain of sources with ": ". The
+    /// code is derived from the first std::io::Error in the chain, if any,
+    /// and is Unknown otherwise.
+    pub fn from_error(err: &(dyn Error + 'static)) -> Status {
+        let mut message = err.to_string();
+        let mut code = io_error_code(err);
+        let mut source = err.source();
+        while let Some(err) = source {
+            message.push_str(": ");
+            message.push_str(&err.to_string());
+            code = code.or_else(|| io_error_code(err));
+            source = err.source();
+        }
+        Status {
+            code: code.unwrap_or(StatusCode::Unknown),
+            message,
+        }
+    }
+}
+
+fn io_error_code(err: &(dyn Error + 'static)) -> Option<StatusCode> {
+    use std::io::ErrorKind;
+
+    let code = match err.downcast_ref::<io::Error>()?.kind() {
+        ErrorKind::NotFound => StatusCode::NotFound,
+        ErrorKind::PermissionDenied => StatusCode::PermissionDenied,
+        ErrorKind::AlreadyExists => StatusCode::AlreadyExists,
+        ErrorKind::TimedOut => StatusCode::DeadlineExceeded,
+        ErrorKind::InvalidInput | ErrorKind::InvalidData => StatusCode::InvalidArgument,
+        ErrorKind::UnexpectedEof => StatusCode::OutOfRange,
+        ErrorKind::Interrupted => StatusCode::Cancelled,
+        ErrorKind::Unsupported => StatusCode::Unimplemented,
+        ErrorKind::OutOfMemory => StatusCode::ResourceExhausted,
+        ErrorKind::ConnectionRefused
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::NotConnected
+        | ErrorKind::BrokenPipe
+        | ErrorKind::AddrNotAvailable => StatusCode::Unavailable,
+        _ => StatusCode::Unknown,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+
+    fn hash(v: &AttributeValue) -> u64 {
+        let mut h = DefaultHasher::new();
+        v.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn double_equality_and_hashing() {
+        let nan = AttributeValue::from(f64::NAN);
+        assert_eq!(nan, nan.clone());
+        assert_eq!(hash(&nan), hash(&nan.clone()));
+
+        assert_ne!(AttributeValue::from(0.0), AttributeValue::from(-0.0));
+        assert_eq!(AttributeValue::from(0.5f32), AttributeValue::from(0.5));
+        assert_eq!(
+            AttributeValue::from(vec![1.5, f64::NAN]),
+            AttributeValue::DoubleArrayAttribute(vec![1.5, f64::NAN])
+        );
+        assert_ne!(
+            AttributeValue::from(vec![1.5]),
+            AttributeValue::from(vec![1.5, 2.5])
+        );
+
+        let set: HashSet<_> = vec![
+            AttributeValue::from(1.0),
+            AttributeValue::from(1.0),
+            AttributeValue::from(f64::NAN),
+            AttributeValue::from(f64::NAN),
+            AttributeValue::Int64Attribute(1),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 3);
+    }
+
+    #[derive(Debug)]
+    struct WrappedError(io::Error);
+
+    impl fmt::Display for WrappedError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "loading config")
+        }
+    }
+
+    impl Error for WrappedError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn status_from_error() {
+        let err = WrappedError(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        assert_eq!(
+            Status::from_error(&err),
+            Status {
+                code: StatusCode::NotFound,
+                message: "loading config: no such file".to_string(),
+            }
+        );
+
+        let err = "bad".parse::<i64>().unwrap_err();
+        assert_eq!(
+            Status::from_error(&err),
+            Status {
+                code: StatusCode::Unknown,
+                message: "invalid digit found in string".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn from_native_types() {
+        assert_eq!(
+            AttributeValue::from(true),
+            AttributeValue::BoolAttribute(true)
+        );
+        assert_eq!(
+            AttributeValue::from(-3i8),
+            AttributeValue::Int64Attribute(-3)
+        );
+        assert_eq!(
+            AttributeValue::from(300i16),
+            AttributeValue::Int64Attribute(300)
+        );
+        assert_eq!(AttributeValue::from(7), AttributeValue::Int64Attribute(7));
+        assert_eq!(
+            AttributeValue::from(7i64),
+            AttributeValue::Int64Attribute(7)
+        );
+        assert_eq!(
+            AttributeValue::from(255u8),
+            AttributeValue::Int64Attribute(255)
+        );
+        assert_eq!(
+            AttributeValue::from(65535u16),
+            AttributeValue::Int64Attribute(65535)
+        );
+        assert_eq!(
+            AttributeValue::from(u32::MAX),
+            AttributeValue::Int64Attribute(i64::from(u32::MAX))
+        );
+        assert_eq!(
+            AttributeValue::try_from(42usize),
+            Ok(AttributeValue::Int64Attribute(42))
+        );
+        assert_eq!(
+            AttributeValue::try_from(-42isize),
+            Ok(AttributeValue::Int64Attribute(-42))
+        );
+        assert_eq!(
+            AttributeValue::try_from(i64::MAX as u64),
+            Ok(AttributeValue::Int64Attribute(i64::MAX))
+        );
+        assert!(AttributeValue::try_from(u64::MAX).is_err());
+        assert!(AttributeValue::try_from(i128::MIN).is_err());
+        assert_eq!(
+            AttributeValue::from("str"),
+            AttributeValue::StringAttribute("str".to_string())
+        );
+        assert_eq!(
+            AttributeValue::from("string".to_string()),
+            AttributeValue::StringAttribute("string".to_string())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "string-attributes")]
+    fn to_string_value() {
+        let test_cases = vec![
+            (AttributeValue::from(true), "true"),
+            (AttributeValue::from(-42), "-42"),
+            (
+                AttributeValue::from("a \"quoted\" value"),
+                "a \"quoted\" value",
+            ),
+            (AttributeValue::from(1.5), "1.5"),
+            (AttributeValue::from(vec![0u8, 1, 2, 255]), "AAEC/w=="),
+            (AttributeValue::from(vec![true, false]), "[true,false]"),
+            (AttributeValue::from(vec![1i64, -2]), "[1,-2]"),
+            (AttributeValue::from(vec![1.5, f64::NAN]), "[1.5,null]"),
+            (
+                AttributeValue::from(vec!["a", "b\"c"]),
+                "[\"a\",\"b\\\"c\"]",
+            ),
+        ];
+        for (value, want) in test_cases {
+            assert_eq!(value.to_string_value(), want, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn attributes_macro() {
+        let empty: Attributes = attributes! {};
+        assert!(empty.is_empty());
+
+        let key = String::from("key3");
+        let got = attributes! {
+            "key1" => "value1",
+            "key2" => 2u16,
+            key => vec![1.5, 2.5],
+        };
+        let mut want = HashMap::new();
+        want.insert(
+            String::from("key1"),
+            AttributeValue::StringAttribute(String::from("value1")),
+        );
+        want.insert(String::from("key2"), AttributeValue::Int64Attribute(2));
+        want.insert(
+            String::from("key3"),
+            AttributeValue::DoubleArrayAttribute(vec![1.5, 2.5]),
+        );
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn ordering_is_by_variant_then_value() {
+        let mut values = vec![
+            AttributeValue::from(vec!["b", "a"]),
+            AttributeValue::from(vec![2.0, 1.0]),
+            AttributeValue::from(vec![2.0]),
+            AttributeValue::from(&b"bytes"[..]),
+            AttributeValue::from(1.0),
+            AttributeValue::from(-1.0),
+            AttributeValue::StringAttribute("s".to_string()),
+            AttributeValue::Int64Attribute(1),
+            AttributeValue::BoolAttribute(true),
+            AttributeValue::from(vec![true]),
+            AttributeValue::from(vec![1i64]),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                AttributeValue::BoolAttribute(true),
+                AttributeValue::Int64Attribute(1),
+                AttributeValue::StringAttribute("s".to_string()),
+                AttributeValue::DoubleAttribute(-1.0),
+                AttributeValue::DoubleAttribute(1.0),
+                AttributeValue::BytesAttribute(b"bytes".to_vec()),
+                AttributeValue::BoolArrayAttribute(vec![true]),
+                AttributeValue::Int64ArrayAttribute(vec![1]),
+                AttributeValue::DoubleArrayAttribute(vec![2.0]),
+                AttributeValue::DoubleArrayAttribute(vec![2.0, 1.0]),
+                AttributeValue::StringArrayAttribute(vec!["b".to_string(), "a".to_string()]),
+            ]
+        );
+    }
+}
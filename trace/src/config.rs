@@ -13,6 +13,50 @@ pub struct Config {
 
     /// id_generator is for internal use only.
     pub id_generator: Arc<dyn IDGenerator + Send + Sync>,
+
+    /// span_limits bounds the data recorded by new spans.
+    pub span_limits: SpanLimits,
+}
+
+/// DEFAULT_MAX_ATTRIBUTES_PER_SPAN is the default maximum number of
+/// attributes per span.
+pub const DEFAULT_MAX_ATTRIBUTES_PER_SPAN: usize = 32;
+/// DEFAULT_MAX_ANNOTATION_EVENTS_PER_SPAN is the default maximum number of
+/// annotation events per span.
+pub const DEFAULT_MAX_ANNOTATION_EVENTS_PER_SPAN: usize = 32;
+/// DEFAULT_MAX_MESSAGE_EVENTS_PER_SPAN is the default maximum number of
+/// message events per span.
+pub const DEFAULT_MAX_MESSAGE_EVENTS_PER_SPAN: usize = 128;
+/// DEFAULT_MAX_LINKS_PER_SPAN is the default maximum number of links per
+/// span.
+pub const DEFAULT_MAX_LINKS_PER_SPAN: usize = 32;
+
+/// SpanLimits bounds the number of attributes, annotations, message events
+/// and links recorded by a span. Once a limit is reached, the oldest data is
+/// dropped and counted in the SpanData.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct SpanLimits {
+    /// max_attributes_per_span is the maximum number of attributes per span.
+    pub max_attributes_per_span: usize,
+    /// max_annotation_events_per_span is the maximum number of annotation
+    /// events per span.
+    pub max_annotation_events_per_span: usize,
+    /// max_message_events_per_span is the maximum number of message events
+    /// per span.
+    pub max_message_events_per_span: usize,
+    /// max_links_per_span is the maximum number of links per span.
+    pub max_links_per_span: usize,
+}
+
+impl Default for SpanLimits {
+    fn default() -> Self {
+        SpanLimits {
+            max_attributes_per_span: DEFAULT_MAX_ATTRIBUTES_PER_SPAN,
+            max_annotation_events_per_span: DEFAULT_MAX_ANNOTATION_EVENTS_PER_SPAN,
+            max_message_events_per_span: DEFAULT_MAX_MESSAGE_EVENTS_PER_SPAN,
+            max_links_per_span: DEFAULT_MAX_LINKS_PER_SPAN,
+        }
+    }
 }
 
 lazy_static! {
@@ -20,6 +64,7 @@ lazy_static! {
     static ref CONFIG: RwLock<Config> = RwLock::new(Config {
         default_sampler: default_sampler(),
         id_generator: default_id_generator(),
+        span_limits: SpanLimits::default(),
     });
 }
 
@@ -35,6 +80,13 @@ pub fn set_global_id_generator(id_generator: &Arc<dyn IDGenerator + Send + Sync>
     c.id_generator = Arc::clone(id_generator);
 }
 
+/// set_global_span_limits will change the limits of spans started from now
+/// on.
+pub fn set_global_span_limits(limits: SpanLimits) {
+    let mut c = CONFIG.write().unwrap();
+    c.span_limits = limits;
+}
+
 /// load_config retrieves a copy of the global tracing configuration.
 pub fn load_config() -> Config {
     let c = CONFIG.read().unwrap();
@@ -53,6 +105,7 @@ mod tests {
         };
         set_global_id_generator(&config.id_generator);
         set_global_default_sampler(&config.default_sampler);
+        set_global_span_limits(config.span_limits);
         let current_cfg = CONFIG.read().unwrap();
 
         assert!(Sampler::ptr_eq(
@@ -60,5 +113,6 @@ mod tests {
             &config.default_sampler
         ));
         assert!(Arc::ptr_eq(&current_cfg.id_generator, &config.id_generator));
+        assert_eq!(current_cfg.span_limits, config.span_limits);
     }
 }
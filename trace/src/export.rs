@@ -1,24 +1,104 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
 use std::sync::{Arc, RwLock};
-use std::time;
+use std::time::{self, SystemTime, UNIX_EPOCH};
 
 use lazy_static::lazy_static;
 
 use crate::basetypes::{Annotation, Attributes, Link, MessageEvent, SpanID, Status};
 use crate::trace::{SpanContext, SpanKind};
 
+mod batch;
+
+pub use self::batch::{
+    BatchConfig, BatchExporter, DEFAULT_FLUSH_INTERVAL, DEFAULT_MAX_BATCH_SIZE,
+    DEFAULT_MAX_QUEUE_SIZE,
+};
+
 /// Exporter is a trait for structs that receive sampled trace spans.
 ///
 /// The export_span method should be safe for concurrent use and should return
 /// quickly; if an Exporter takes a significant amount of time to process a
 /// SpanData, that work should be done on another thread or in a future.
+/// Wrapping the Exporter in a BatchExporter, with BatchExporter::from_exporter,
+/// does that.
 pub trait Exporter {
+    /// export_span receives a span that has ended.
     fn export_span(&self, s: &SpanData);
+
+    /// force_flush exports any spans the Exporter holds on to, blocking until
+    /// it is done.
+    fn force_flush(&self) -> Result<(), ExportError> {
+        Ok(())
+    }
+
+    /// shutdown flushes the Exporter and releases its resources. Spans
+    /// received afterwards may be dropped.
+    fn shutdown(&self) -> Result<(), ExportError> {
+        Ok(())
+    }
+}
+
+/// SpanExporter is a trait for structs that send batches of spans to a
+/// backend, and can report failures.
+///
+/// SpanExporters are usually wrapped in a BatchExporter, which calls export
+/// from a background thread, before being registered.
+pub trait SpanExporter {
+    /// export sends a batch of spans that have ended.
+    fn export(&self, spans: &[SpanData]) -> Result<(), ExportError>;
+
+    /// force_flush sends any spans the SpanExporter holds on to, blocking
+    /// until it is done.
+    fn force_flush(&self) -> Result<(), ExportError> {
+        Ok(())
+    }
+
+    /// shutdown flushes the SpanExporter and releases its resources.
+    fn shutdown(&self) -> Result<(), ExportError> {
+        Ok(())
+    }
+}
+
+/// ExportError is returned by exporters that failed to export spans.
+#[derive(Debug)]
+pub enum ExportError {
+    /// ShutDown is returned when the exporter has already been shut down.
+    ShutDown,
+    /// Other wraps the error the export failed with.
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl ExportError {
+    /// other returns an ExportError wrapping err.
+    pub fn other(err: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        ExportError::Other(err.into())
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::ShutDown => write!(f, "exporter is shut down"),
+            ExportError::Other(err) => write!(f, "export failed: {}", err),
+        }
+    }
+}
+
+impl Error for ExportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ExportError::ShutDown => None,
+            ExportError::Other(err) => Some(err.as_ref()),
+        }
+    }
 }
 
 type Exporters = RwLock<Vec<Arc<dyn Exporter + Send + Sync>>>;
 
 lazy_static! {
-    pub static ref EXPORTERS: Exporters = { RwLock::new(Vec::new()) };
+    pub static ref EXPORTERS: Exporters = RwLock::new(Vec::new());
 }
 
 /// register_exporter adds to the list of Exporters that will receive sampled
@@ -48,22 +128,145 @@ pub fn unregister_exporter(e: &Arc<dyn Exporter + Send + Sync>) {
         .collect();
 }
 
+/// shutdown_exporters unregisters all the Exporters and shuts them down in
+/// the order they were registered, so that their last spans are exported
+/// before the process exits.
+///
+/// Every Exporter is shut down even if one fails, the first error is
+/// returned.
+pub fn shutdown_exporters() -> Result<(), ExportError> {
+    let exporters = mem::take(&mut *EXPORTERS.write().unwrap());
+    shutdown_all(exporters)
+}
+
+fn shutdown_all(exporters: Vec<Arc<dyn Exporter + Send + Sync>>) -> Result<(), ExportError> {
+    let mut result = Ok(());
+    for exporter in exporters {
+        if let Err(err) = exporter.shutdown() {
+            if result.is_ok() {
+                result = Err(err);
+            }
+        }
+    }
+    result
+}
+
 /// SpanData contains all the information collected by a Span.
 #[derive(Debug, Clone, PartialEq)]
 pub struct SpanData {
+    /// The span context of the span.
     pub span_context: SpanContext,
+    /// The span ID of the parent span, if any.
     pub parent_span_id: Option<SpanID>,
+    /// The kind of the span.
     pub span_kind: SpanKind,
+    /// The name of the span.
     pub name: String,
-    pub start_time: time::Instant,
-    /// The wall clock time of EndTime will be adjusted to always be offset
-    /// from StartTime by the duration of the span.
-    pub end_time: Option<time::Instant>,
-    /// The values of Attributes each have type string, bool, or int64.
+    /// The wall clock time the span started at.
+    pub start_time: SystemTime,
+    /// The wall clock time of end_time will be adjusted to always be offset
+    /// from start_time by the duration of the span, as measured by a
+    /// monotonic clock. The times of annotations and message events are
+    /// derived the same way.
+    pub end_time: Option<SystemTime>,
+    /// The attributes of the span, see AttributeValue for the supported types.
     pub attributes: Attributes,
+    /// The annotations of the span, oldest first.
     pub annotations: Vec<Annotation>,
+    /// The message events of the span, oldest first.
     pub message_events: Vec<MessageEvent>,
+    /// The status of the span, if one was set.
     pub status: Option<Status>,
+    /// The links of the span, oldest first.
     pub links: Vec<Link>,
+    /// Whether the parent span is in another process.
     pub has_remote_parent: bool,
+    /// The number of attributes dropped because of the span limits.
+    pub dropped_attribute_count: usize,
+    /// The number of annotations dropped because of the span limits.
+    pub dropped_annotation_count: usize,
+    /// The number of message events dropped because of the span limits.
+    pub dropped_message_event_count: usize,
+    /// The number of links dropped because of the span limits.
+    pub dropped_link_count: usize,
+}
+
+/// epoch_micros returns the number of microseconds between the Unix epoch
+/// and t, or zero if t is before the epoch.
+pub fn epoch_micros(t: SystemTime) -> u64 {
+    epoch_duration(t).as_micros() as u64
+}
+
+/// epoch_nanos returns the number of nanoseconds between the Unix epoch and
+/// t, or zero if t is before the epoch.
+pub fn epoch_nanos(t: SystemTime) -> u64 {
+    epoch_duration(t).as_nanos() as u64
+}
+
+fn epoch_duration(t: SystemTime) -> time::Duration {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    struct ShutdownRecorder {
+        name: &'static str,
+        fail: bool,
+        order: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Exporter for ShutdownRecorder {
+        fn export_span(&self, _: &SpanData) {}
+
+        fn shutdown(&self) -> Result<(), ExportError> {
+            self.order.lock().unwrap().push(self.name);
+            if self.fail {
+                Err(ExportError::other(format!("{} failed", self.name)))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn shutdown_all_in_order() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let exporters: Vec<Arc<dyn Exporter + Send + Sync>> = vec![
+            Arc::new(ShutdownRecorder {
+                name: "first",
+                fail: false,
+                order: Arc::clone(&order),
+            }),
+            Arc::new(ShutdownRecorder {
+                name: "second",
+                fail: true,
+                order: Arc::clone(&order),
+            }),
+            Arc::new(ShutdownRecorder {
+                name: "third",
+                fail: true,
+                order: Arc::clone(&order),
+            }),
+        ];
+
+        let err = shutdown_all(exporters).unwrap_err();
+        assert_eq!(err.to_string(), "export failed: second failed");
+        assert_eq!(*order.lock().unwrap(), vec!["first", "second", "third"]);
+    }
+
+    #[test]
+    fn epoch_timestamps() {
+        let t = UNIX_EPOCH + Duration::new(1_546_300_800, 123_456_789);
+        assert_eq!(epoch_micros(t), 1_546_300_800_123_456);
+        assert_eq!(epoch_nanos(t), 1_546_300_800_123_456_789);
+
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(epoch_micros(before_epoch), 0);
+        assert_eq!(epoch_nanos(before_epoch), 0);
+    }
 }
@@ -0,0 +1,423 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::export::{ExportError, Exporter, SpanData, SpanExporter};
+
+/// DEFAULT_MAX_QUEUE_SIZE is the default number of spans a BatchExporter
+/// queues before dropping spans.
+pub const DEFAULT_MAX_QUEUE_SIZE: usize = 2048;
+/// DEFAULT_MAX_BATCH_SIZE is the default number of spans that make a
+/// BatchExporter export a batch.
+pub const DEFAULT_MAX_BATCH_SIZE: usize = 512;
+/// DEFAULT_FLUSH_INTERVAL is the default longest time a BatchExporter holds
+/// on to a span before exporting it.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// BatchConfig configures a BatchExporter.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct BatchConfig {
+    /// max_queue_size is the number of spans that can be queued before
+    /// further spans are dropped.
+    pub max_queue_size: usize,
+    /// max_batch_size is the number of queued spans that triggers an export.
+    pub max_batch_size: usize,
+    /// flush_interval is the longest time a span is queued before it is
+    /// exported.
+    pub flush_interval: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_queue_size: DEFAULT_MAX_QUEUE_SIZE,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+        }
+    }
+}
+
+enum Message {
+    Span(Box<SpanData>),
+    Flush(SyncSender<Result<(), ExportError>>),
+    Shutdown(SyncSender<Result<(), ExportError>>),
+}
+
+/// BatchExporter is an Exporter that queues spans and hands them to a
+/// SpanExporter in batches on a background thread, so that ending a span
+/// never waits for the export.
+///
+/// A batch is exported once max_batch_size spans are queued or the
+/// flush_interval has passed. Spans that don't fit in the queue are dropped
+/// and counted, as are spans in batches that fail to export. Call shutdown,
+/// or shutdown_exporters, before the process exits so that queued spans
+/// aren't lost.
+pub struct BatchExporter {
+    sender: SyncSender<Message>,
+    counters: Arc<Counters>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[derive(Default)]
+struct Counters {
+    dropped: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl BatchExporter {
+    /// new returns a BatchExporter that exports to inner and starts its
+    /// background thread.
+    pub fn new(inner: Arc<dyn SpanExporter + Send + Sync>, config: BatchConfig) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(config.max_queue_size);
+        let counters = Arc::new(Counters::default());
+        let worker_counters = Arc::clone(&counters);
+        let worker = thread::Builder::new()
+            .name("opencensus-batch-exporter".to_string())
+            .spawn(move || run(inner.as_ref(), &receiver, config, &worker_counters))
+            .expect("failed to spawn batch exporter thread");
+
+        BatchExporter {
+            sender,
+            counters,
+            worker: Mutex::new(Some(worker)),
+        }
+    }
+
+    /// from_exporter returns a BatchExporter that hands each span to inner on
+    /// the background thread, for Exporters that are slow to export a span.
+    pub fn from_exporter(inner: Arc<dyn Exporter + Send + Sync>, config: BatchConfig) -> Self {
+        BatchExporter::new(Arc::new(ExporterAdapter(inner)), config)
+    }
+
+    /// dropped_spans returns the number of spans dropped because the queue was
+    /// full or the exporter was shut down.
+    pub fn dropped_spans(&self) -> u64 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+
+    /// failed_spans returns the number of spans in batches the SpanExporter
+    /// failed to export.
+    pub fn failed_spans(&self) -> u64 {
+        self.counters.failed.load(Ordering::Relaxed)
+    }
+}
+
+impl Exporter for BatchExporter {
+    fn export_span(&self, s: &SpanData) {
+        if self
+            .sender
+            .try_send(Message::Span(Box::new(s.clone())))
+            .is_err()
+        {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// force_flush exports all the spans queued so far and flushes the
+    /// SpanExporter.
+    fn force_flush(&self) -> Result<(), ExportError> {
+        let (done, wait) = mpsc::sync_channel(1);
+        self.sender
+            .send(Message::Flush(done))
+            .map_err(|_| ExportError::ShutDown)?;
+        wait.recv().map_err(|_| ExportError::ShutDown)?
+    }
+
+    /// shutdown exports all the queued spans, shuts the SpanExporter down and
+    /// stops the background thread.
+    fn shutdown(&self) -> Result<(), ExportError> {
+        let worker = self
+            .worker
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or(ExportError::ShutDown)?;
+        let (done, wait) = mpsc::sync_channel(1);
+        let sent = self.sender.send(Message::Shutdown(done));
+        let _ = worker.join();
+        sent.map_err(|_| ExportError::ShutDown)?;
+        wait.recv().map_err(|_| ExportError::ShutDown)?
+    }
+}
+
+impl Drop for BatchExporter {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+/// ExporterAdapter exports batches to an Exporter one span at a time.
+struct ExporterAdapter(Arc<dyn Exporter + Send + Sync>);
+
+impl SpanExporter for ExporterAdapter {
+    fn export(&self, spans: &[SpanData]) -> Result<(), ExportError> {
+        for s in spans {
+            self.0.export_span(s);
+        }
+        Ok(())
+    }
+
+    fn force_flush(&self) -> Result<(), ExportError> {
+        self.0.force_flush()
+    }
+
+    fn shutdown(&self) -> Result<(), ExportError> {
+        self.0.shutdown()
+    }
+}
+
+fn run(
+    inner: &(dyn SpanExporter + Send + Sync),
+    receiver: &Receiver<Message>,
+    config: BatchConfig,
+    counters: &Counters,
+) {
+    let mut batch = Vec::with_capacity(config.max_batch_size);
+    let mut deadline = Instant::now() + config.flush_interval;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(timeout) {
+            Ok(Message::Span(s)) => {
+                batch.push(*s);
+                if batch.len() >= config.max_batch_size {
+                    let _ = export_batch(inner, &mut batch, counters);
+                    deadline = Instant::now() + config.flush_interval;
+                }
+            }
+            Ok(Message::Flush(done)) => {
+                let result = export_batch(inner, &mut batch, counters).and(inner.force_flush());
+                let _ = done.send(result);
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                let _ = export_batch(inner, &mut batch, counters);
+                deadline = Instant::now() + config.flush_interval;
+            }
+            Ok(Message::Shutdown(done)) => {
+                let result = export_batch(inner, &mut batch, counters).and(inner.shutdown());
+                let _ = done.send(result);
+                return;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                let _ = export_batch(inner, &mut batch, counters);
+                let _ = inner.shutdown();
+                return;
+            }
+        }
+    }
+}
+
+fn export_batch(
+    inner: &(dyn SpanExporter + Send + Sync),
+    batch: &mut Vec<SpanData>,
+    counters: &Counters,
+) -> Result<(), ExportError> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let result = inner.export(batch);
+    if result.is_err() {
+        counters
+            .failed
+            .fetch_add(batch.len() as u64, Ordering::Relaxed);
+    }
+    batch.clear();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_util::{span_data, TestExporter};
+
+    struct ChannelExporter {
+        sender: Mutex<mpsc::Sender<String>>,
+        // export blocks after sending while the lock is held by a test.
+        gate: Mutex<()>,
+        // export fails for spans with this name.
+        fail_on: Option<&'static str>,
+        shut_down: Mutex<bool>,
+    }
+
+    impl SpanExporter for ChannelExporter {
+        fn export(&self, spans: &[SpanData]) -> Result<(), ExportError> {
+            for s in spans {
+                self.sender.lock().unwrap().send(s.name.clone()).unwrap();
+                let _gate = self.gate.lock().unwrap();
+                if Some(s.name.as_str()) == self.fail_on {
+                    return Err(ExportError::other("backend unavailable"));
+                }
+            }
+            Ok(())
+        }
+
+        fn shutdown(&self) -> Result<(), ExportError> {
+            *self.shut_down.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    fn channel_exporter() -> (Arc<ChannelExporter>, mpsc::Receiver<String>) {
+        failing_exporter(None)
+    }
+
+    fn failing_exporter(
+        fail_on: Option<&'static str>,
+    ) -> (Arc<ChannelExporter>, mpsc::Receiver<String>) {
+        let (sender, receiver) = mpsc::channel();
+        let exporter = Arc::new(ChannelExporter {
+            sender: Mutex::new(sender),
+            gate: Mutex::new(()),
+            fail_on,
+            shut_down: Mutex::new(false),
+        });
+        (exporter, receiver)
+    }
+
+    const LONG: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn exports_full_batches() {
+        let (inner, exported) = channel_exporter();
+        let exporter = BatchExporter::new(
+            inner,
+            BatchConfig {
+                max_batch_size: 2,
+                flush_interval: LONG,
+                ..BatchConfig::default()
+            },
+        );
+
+        exporter.export_span(&span_data("a"));
+        assert!(exported.recv_timeout(Duration::from_millis(50)).is_err());
+        exporter.export_span(&span_data("b"));
+        assert_eq!(exported.recv_timeout(LONG).unwrap(), "a");
+        assert_eq!(exported.recv_timeout(LONG).unwrap(), "b");
+    }
+
+    #[test]
+    fn exports_after_flush_interval() {
+        let (inner, exported) = channel_exporter();
+        let exporter = BatchExporter::new(
+            inner,
+            BatchConfig {
+                flush_interval: Duration::from_millis(10),
+                ..BatchConfig::default()
+            },
+        );
+
+        exporter.export_span(&span_data("a"));
+        assert_eq!(exported.recv_timeout(LONG).unwrap(), "a");
+    }
+
+    #[test]
+    fn flush_and_shutdown_export_queued_spans() {
+        let (inner, exported) = channel_exporter();
+        let exporter = BatchExporter::new(
+            Arc::clone(&inner) as Arc<dyn SpanExporter + Send + Sync>,
+            BatchConfig {
+                flush_interval: LONG,
+                ..BatchConfig::default()
+            },
+        );
+
+        exporter.export_span(&span_data("a"));
+        exporter.force_flush().unwrap();
+        assert_eq!(exported.try_recv().unwrap(), "a");
+
+        exporter.export_span(&span_data("b"));
+        exporter.shutdown().unwrap();
+        assert_eq!(exported.try_recv().unwrap(), "b");
+        assert!(*inner.shut_down.lock().unwrap());
+
+        exporter.export_span(&span_data("c"));
+        assert!(matches!(exporter.force_flush(), Err(ExportError::ShutDown)));
+        assert!(matches!(exporter.shutdown(), Err(ExportError::ShutDown)));
+        assert!(exported.try_recv().is_err());
+        assert_eq!(exporter.dropped_spans(), 1);
+    }
+
+    #[test]
+    fn counts_spans_that_fail_to_export() {
+        let (inner, exported) = failing_exporter(Some("b"));
+        let exporter = BatchExporter::new(
+            inner,
+            BatchConfig {
+                flush_interval: LONG,
+                ..BatchConfig::default()
+            },
+        );
+
+        exporter.export_span(&span_data("a"));
+        exporter.export_span(&span_data("b"));
+        exporter.export_span(&span_data("c"));
+        let err = exporter.force_flush().unwrap_err();
+        assert_eq!(err.to_string(), "export failed: backend unavailable");
+
+        let got: Vec<_> = exported.try_iter().collect();
+        assert_eq!(got, vec!["a", "b"]);
+        assert_eq!(exporter.failed_spans(), 3);
+        assert_eq!(exporter.dropped_spans(), 0);
+
+        exporter.export_span(&span_data("d"));
+        exporter.force_flush().unwrap();
+        assert_eq!(exported.try_recv().unwrap(), "d");
+        assert_eq!(exporter.failed_spans(), 3);
+    }
+
+    #[test]
+    fn drops_spans_when_queue_is_full() {
+        let (inner, exported) = channel_exporter();
+        let gate = inner.gate.lock().unwrap();
+        let exporter = BatchExporter::new(
+            Arc::clone(&inner) as Arc<dyn SpanExporter + Send + Sync>,
+            BatchConfig {
+                max_queue_size: 1,
+                max_batch_size: 1,
+                flush_interval: LONG,
+            },
+        );
+
+        // the first span blocks the worker in export, the second fills
+        // the queue and the rest are dropped.
+        exporter.export_span(&span_data("a"));
+        assert_eq!(exported.recv_timeout(LONG).unwrap(), "a");
+        for name in &["b", "c", "d"] {
+            exporter.export_span(&span_data(name));
+        }
+        assert_eq!(exporter.dropped_spans(), 2);
+
+        drop(gate);
+        exporter.shutdown().unwrap();
+        let got: Vec<_> = exported.try_iter().collect();
+        assert_eq!(got, vec!["b"]);
+    }
+
+    #[test]
+    fn exports_to_an_exporter() {
+        let inner = Arc::new(TestExporter::default());
+        let exporter = BatchExporter::from_exporter(
+            Arc::clone(&inner) as Arc<dyn Exporter + Send + Sync>,
+            BatchConfig {
+                flush_interval: LONG,
+                ..BatchConfig::default()
+            },
+        );
+
+        exporter.export_span(&span_data("a"));
+        exporter.export_span(&span_data("b"));
+        assert!(inner.exported_spans.lock().unwrap().is_empty());
+        exporter.shutdown().unwrap();
+
+        let got: Vec<_> = inner
+            .exported_spans
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| s.name.clone())
+            .collect();
+        assert_eq!(got, vec!["a", "b"]);
+    }
+}
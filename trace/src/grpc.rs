@@ -0,0 +1,666 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use ::http::header::{HeaderMap, HeaderValue};
+use ::http::{Request, Response};
+use base64::alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use http_body::{Body, Frame, SizeHint};
+use tonic::metadata::MetadataValue;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::basetypes::Status;
+use crate::propagation::{from_binary, to_binary};
+use crate::sampling::Sampler;
+use crate::status_codes::StatusCode;
+use crate::trace::{start_span_internal, Span, SpanContext, SpanKind, StartOptions};
+
+/// TRACE_BIN_HEADER is the metadata key carrying the binary format of the
+/// span context.
+pub const TRACE_BIN_HEADER: &str = "grpc-trace-bin";
+
+// binary metadata is sent unpadded but either form must be accepted.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// inject_headers writes the span context into the grpc-trace-bin header.
+pub fn inject_headers(sc: &SpanContext, headers: &mut HeaderMap) {
+    if let Ok(value) = HeaderValue::from_str(&BASE64.encode(to_binary(sc))) {
+        headers.insert(TRACE_BIN_HEADER, value);
+    }
+}
+
+/// extract_headers returns the span context in the grpc-trace-bin header,
+/// if any.
+pub fn extract_headers(headers: &HeaderMap) -> Option<SpanContext> {
+    let bin = BASE64
+        .decode(headers.get(TRACE_BIN_HEADER)?.as_bytes())
+        .ok()?;
+    from_binary(&bin).ok()
+}
+
+/// client_interceptor is a tonic interceptor that propagates the span context
+/// of the Span in the request extensions, if any.
+pub fn client_interceptor(
+    mut req: tonic::Request<()>,
+) -> Result<tonic::Request<()>, tonic::Status> {
+    if let Some(span) = req.extensions().get::<Span>() {
+        let value = MetadataValue::from_bytes(&to_binary(span.span_context()));
+        req.metadata_mut().insert_bin(TRACE_BIN_HEADER, value);
+    }
+    Ok(req)
+}
+
+/// server_interceptor is a tonic interceptor that adds the span context
+/// propagated by the client, if any, to the request extensions.
+///
+/// Handlers can use it as the parent of start_span_with_remote_parent.
+pub fn server_interceptor(
+    mut req: tonic::Request<()>,
+) -> Result<tonic::Request<()>, tonic::Status> {
+    let sc = req
+        .metadata()
+        .get_bin(TRACE_BIN_HEADER)
+        .and_then(|value| value.to_bytes().ok())
+        .and_then(|bin| from_binary(&bin).ok());
+    if let Some(sc) = sc {
+        req.extensions_mut().insert(sc);
+    }
+    Ok(req)
+}
+
+/// ClientLayer traces outgoing gRPC requests.
+///
+/// Each request gets a client span named "Sent.<service>.<method>" that is a
+/// child of the Span in the request extensions, if any. The span is
+/// propagated in the grpc-trace-bin header and ended with the gRPC status of
+/// the response once its body is finished.
+#[derive(Clone, Default)]
+pub struct ClientLayer {
+    /// sampler is consulted for new client spans if set.
+    pub sampler: Option<Sampler>,
+}
+
+impl<S> Layer<S> for ClientLayer {
+    type Service = ClientService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientService {
+            inner,
+            sampler: self.sampler.clone(),
+        }
+    }
+}
+
+/// ClientService is the service produced by ClientLayer.
+#[derive(Clone)]
+pub struct ClientService<S> {
+    inner: S,
+    sampler: Option<Sampler>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ClientService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: fmt::Display,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let opts = StartOptions {
+            sampler: self.sampler.clone(),
+            span_kind: SpanKind::Client,
+            ..StartOptions::default()
+        };
+        let parent = req
+            .extensions()
+            .get::<Span>()
+            .map(|span| span.span_context().clone());
+        let name = span_name("Sent", req.uri().path());
+        let span = start_span_internal(&name, parent.as_ref(), false, &opts);
+        inject_headers(span.span_context(), req.headers_mut());
+
+        ResponseFuture {
+            inner: Box::pin(self.inner.call(req)),
+            span: Some(span),
+        }
+    }
+}
+
+/// ServerLayer traces incoming gRPC requests.
+///
+/// Each request gets a server span named "Recv.<service>.<method>" whose
+/// remote parent is read from the grpc-trace-bin header, if any. The span is
+/// added to the request extensions so handlers can start child spans, and is
+/// ended with the gRPC status of the response once its body is finished.
+#[derive(Clone, Default)]
+pub struct ServerLayer {
+    /// sampler is consulted for new server spans if set.
+    pub sampler: Option<Sampler>,
+}
+
+impl<S> Layer<S> for ServerLayer {
+    type Service = ServerService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ServerService {
+            inner,
+            sampler: self.sampler.clone(),
+        }
+    }
+}
+
+/// ServerService is the service produced by ServerLayer.
+#[derive(Clone)]
+pub struct ServerService<S> {
+    inner: S,
+    sampler: Option<Sampler>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ServerService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: fmt::Display,
+    ResBody: Body,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let opts = StartOptions {
+            sampler: self.sampler.clone(),
+            span_kind: SpanKind::Server,
+            ..StartOptions::default()
+        };
+        let name = span_name("Recv", req.uri().path());
+        let span = match extract_headers(req.headers()) {
+            Some(remote_parent) => start_span_internal(&name, Some(&remote_parent), true, &opts),
+            None => start_span_internal(&name, None, false, &opts),
+        };
+        req.extensions_mut().insert(span.clone());
+
+        ResponseFuture {
+            inner: Box::pin(self.inner.call(req)),
+            span: Some(span),
+        }
+    }
+}
+
+/// ResponseFuture passes the span of a traced request on to the body of the
+/// response once it is ready.
+///
+/// If the call fails, the span is ended with the error. If the future is
+/// dropped before the response is ready, the span is ended as cancelled.
+pub struct ResponseFuture<F> {
+    inner: Pin<Box<F>>,
+    span: Option<Span>,
+}
+
+impl<F, ResBody, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+    E: fmt::Display,
+    ResBody: Body,
+{
+    type Output = Result<Response<ResponseBody<ResBody>>, E>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = ready!(self.inner.as_mut().poll(cx));
+        let span = self.span.take();
+        match result {
+            Ok(res) => {
+                // a trailers-only response carries its status in the headers.
+                let status = tonic::Status::from_header_map(res.headers()).map(grpc_status);
+                Poll::Ready(Ok(res.map(|body| ResponseBody {
+                    inner: Box::pin(body),
+                    span,
+                    status,
+                })))
+            }
+            Err(e) => {
+                if let Some(mut span) = span {
+                    span.set_status(&Status {
+                        code: StatusCode::Unknown,
+                        message: e.to_string(),
+                    });
+                    span.end();
+                }
+                Poll::Ready(Err(e))
+            }
+        }
+    }
+}
+
+impl<F> Drop for ResponseFuture<F> {
+    fn drop(&mut self) {
+        if let Some(mut span) = self.span.take() {
+            span.set_status(&Status {
+                code: StatusCode::Cancelled,
+                message: String::new(),
+            });
+            span.end();
+        }
+    }
+}
+
+/// ResponseBody ends the span of a traced request once the body of the
+/// response is finished.
+///
+/// The span is ended with the gRPC status of the trailers, or of the headers
+/// of a trailers-only response. A response without a status is ended as
+/// unknown, and if the body is dropped before it is finished, the span is
+/// ended as cancelled.
+pub struct ResponseBody<B: Body> {
+    inner: Pin<Box<B>>,
+    span: Option<Span>,
+    status: Option<Status>,
+}
+
+impl<B: Body> ResponseBody<B> {
+    fn end(&mut self, status: Status) {
+        if let Some(mut span) = self.span.take() {
+            span.set_status(&status);
+            span.end();
+        }
+    }
+
+    fn end_with_response_status(&mut self) {
+        let status = self.status.take().unwrap_or_else(|| Status {
+            code: StatusCode::Unknown,
+            message: "missing grpc-status".to_string(),
+        });
+        self.end(status);
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body,
+    B::Error: fmt::Display,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(self.inner.as_mut().poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(trailers) = frame.trailers_ref() {
+                    if let Some(status) = tonic::Status::from_header_map(trailers) {
+                        self.status = Some(grpc_status(status));
+                    }
+                }
+            }
+            Some(Err(e)) => self.end(Status {
+                code: StatusCode::Unknown,
+                message: e.to_string(),
+            }),
+            None => self.end_with_response_status(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B: Body> Drop for ResponseBody<B> {
+    fn drop(&mut self) {
+        // bodies that are empty or already at their end may never be polled
+        // to completion.
+        if self.status.is_some() || self.inner.is_end_stream() {
+            self.end_with_response_status();
+        } else {
+            self.end(Status {
+                code: StatusCode::Cancelled,
+                message: String::new(),
+            });
+        }
+    }
+}
+
+/// span_name converts a gRPC path such as /helloworld.Greeter/SayHello to a
+/// span name such as Sent.helloworld.Greeter.SayHello.
+fn span_name(prefix: &str, path: &str) -> String {
+    format!(
+        "{}.{}",
+        prefix,
+        path.trim_start_matches('/').replace('/', ".")
+    )
+}
+
+fn grpc_status(status: tonic::Status) -> Status {
+    Status {
+        code: status_code(status.code()),
+        message: status.message().to_string(),
+    }
+}
+
+fn status_code(code: tonic::Code) -> StatusCode {
+    match code {
+        tonic::Code::Ok => StatusCode::OK,
+        tonic::Code::Cancelled => StatusCode::Cancelled,
+        tonic::Code::Unknown => StatusCode::Unknown,
+        tonic::Code::InvalidArgument => StatusCode::InvalidArgument,
+        tonic::Code::DeadlineExceeded => StatusCode::DeadlineExceeded,
+        tonic::Code::NotFound => StatusCode::NotFound,
+        tonic::Code::AlreadyExists => StatusCode::AlreadyExists,
+        tonic::Code::PermissionDenied => StatusCode::PermissionDenied,
+        tonic::Code::ResourceExhausted => StatusCode::ResourceExhausted,
+        tonic::Code::FailedPrecondition => StatusCode::FailedPrecondition,
+        tonic::Code::Aborted => StatusCode::Aborted,
+        tonic::Code::OutOfRange => StatusCode::OutOfRange,
+        tonic::Code::Unimplemented => StatusCode::Unimplemented,
+        tonic::Code::Internal => StatusCode::Internal,
+        tonic::Code::Unavailable => StatusCode::Unavailable,
+        tonic::Code::DataLoss => StatusCode::DataLoss,
+        tonic::Code::Unauthenticated => StatusCode::Unauthenticated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+    use std::future::{poll_fn, ready, Ready};
+    use std::sync::{Arc, Mutex};
+
+    use futures_executor::block_on;
+    use io_context::Context as IoContext;
+
+    use crate::basetypes::{SpanID, TraceID};
+    use crate::export::{register_exporter, unregister_exporter, Exporter, SpanData};
+    use crate::sampling::always_sample;
+    use crate::test_util::TestExporter;
+    use crate::trace::{start_span, with_sampler, TraceOptions};
+
+    const TID: TraceID = TraceID([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 4, 8, 16, 32, 64, 128]);
+    const SID: SpanID = SpanID([1, 2, 4, 8, 16, 32, 64, 128]);
+
+    /// TestBody is a response body with a single message followed by the
+    /// trailers, if any.
+    struct TestBody {
+        message: Option<&'static [u8]>,
+        trailers: Option<HeaderMap>,
+    }
+
+    impl Body for TestBody {
+        type Data = &'static [u8];
+        type Error = Infallible;
+
+        fn poll_frame(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+            if let Some(message) = self.message.take() {
+                return Poll::Ready(Some(Ok(Frame::data(message))));
+            }
+            Poll::Ready(self.trailers.take().map(|t| Ok(Frame::trailers(t))))
+        }
+
+        fn is_end_stream(&self) -> bool {
+            self.message.is_none() && self.trailers.is_none()
+        }
+    }
+
+    /// TestService records the requests it receives and responds with the
+    /// given headers, message and trailers.
+    #[derive(Clone)]
+    struct TestService {
+        requests: Arc<Mutex<Vec<Request<()>>>>,
+        headers: HeaderMap,
+        message: Option<&'static [u8]>,
+        trailers: Option<HeaderMap>,
+    }
+
+    impl Service<Request<()>> for TestService {
+        type Response = Response<TestBody>;
+        type Error = Infallible;
+        type Future = Ready<Result<Response<TestBody>, Infallible>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Request<()>) -> Self::Future {
+            self.requests.lock().unwrap().push(req);
+            let mut res = Response::new(TestBody {
+                message: self.message,
+                trailers: self.trailers.clone(),
+            });
+            *res.headers_mut() = self.headers.clone();
+            ready(Ok(res))
+        }
+    }
+
+    fn status_trailers(code: &'static str, message: &'static str) -> HeaderMap {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static(code));
+        trailers.insert("grpc-message", HeaderValue::from_static(message));
+        trailers
+    }
+
+    /// read_body polls body until it is finished.
+    fn read_body<B: Body + Unpin>(mut body: B) {
+        while block_on(poll_fn(|cx| Pin::new(&mut body).poll_frame(cx))).is_some() {}
+    }
+
+    fn exported_span(te: &TestExporter, name: &str) -> Option<SpanData> {
+        te.exported_spans
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| s.name == name && s.span_context.trace_id == TID)
+            .cloned()
+    }
+
+    fn sampled_span_context() -> SpanContext {
+        SpanContext {
+            trace_id: TID,
+            span_id: SID,
+            trace_options: TraceOptions(1),
+            trace_state: None,
+        }
+    }
+
+    #[test]
+    fn headers_roundtrip() {
+        let sc = sampled_span_context();
+        let mut headers = HeaderMap::new();
+        inject_headers(&sc, &mut headers);
+        assert_eq!(extract_headers(&headers), Some(sc.clone()));
+
+        let padded = format!("{}=", headers[TRACE_BIN_HEADER].to_str().unwrap());
+        headers.insert(TRACE_BIN_HEADER, HeaderValue::from_str(&padded).unwrap());
+        assert_eq!(extract_headers(&headers), Some(sc));
+    }
+
+    #[test]
+    fn interceptors_propagate_span_context() {
+        let (_, span) = start_span(
+            &IoContext::background().freeze(),
+            "parent",
+            &[with_sampler(always_sample())],
+        );
+        let mut req = tonic::Request::new(());
+        req.extensions_mut().insert(span.clone());
+
+        let req = client_interceptor(req).unwrap();
+        let (metadata, _, _) = req.into_parts();
+        let req = tonic::Request::from_parts(metadata, Default::default(), ());
+
+        let req = server_interceptor(req).unwrap();
+        assert_eq!(
+            req.extensions().get::<SpanContext>(),
+            Some(span.span_context())
+        );
+    }
+
+    #[test]
+    fn client_and_server_layers() {
+        let te = Arc::new(TestExporter::default());
+        let dyn_te: Arc<dyn Exporter + Send + Sync> = te.clone();
+        register_exporter(Arc::clone(&dyn_te));
+
+        let server_requests = Arc::new(Mutex::new(Vec::new()));
+        let mut server = ServerLayer::default().layer(TestService {
+            requests: Arc::clone(&server_requests),
+            headers: HeaderMap::new(),
+            message: Some(b"message"),
+            trailers: Some(status_trailers("5", "no%20such%20user")),
+        });
+
+        let client_requests = Arc::new(Mutex::new(Vec::new()));
+        let mut client = ClientLayer {
+            sampler: Some(always_sample()),
+        }
+        .layer(TestService {
+            requests: Arc::clone(&client_requests),
+            headers: HeaderMap::new(),
+            message: Some(b"message"),
+            trailers: Some(status_trailers("0", "")),
+        });
+
+        let parent = start_span_internal(
+            "parent",
+            Some(&sampled_span_context()),
+            true,
+            &StartOptions::default(),
+        );
+        let mut req = Request::post("/helloworld.Greeter/SayHello")
+            .body(())
+            .unwrap();
+        req.extensions_mut().insert(parent.clone());
+        let res = block_on(client.call(req)).unwrap();
+        assert!(exported_span(&te, "Sent.helloworld.Greeter.SayHello").is_none());
+        read_body(res.into_body());
+
+        let sent = client_requests.lock().unwrap().pop().unwrap();
+        let mut req = Request::post("/helloworld.Greeter/SayHello")
+            .body(())
+            .unwrap();
+        *req.headers_mut() = sent.headers().clone();
+        let res = block_on(server.call(req)).unwrap();
+        assert!(exported_span(&te, "Recv.helloworld.Greeter.SayHello").is_none());
+        read_body(res.into_body());
+
+        unregister_exporter(&dyn_te);
+
+        let received = server_requests.lock().unwrap().pop().unwrap();
+        let server_span = received.extensions().get::<Span>().unwrap();
+
+        let client_data = exported_span(&te, "Sent.helloworld.Greeter.SayHello").unwrap();
+        let server_data = exported_span(&te, "Recv.helloworld.Greeter.SayHello").unwrap();
+
+        assert_eq!(client_data.span_kind, SpanKind::Client);
+        assert_eq!(
+            client_data.parent_span_id,
+            Some(parent.span_context().span_id)
+        );
+        assert_eq!(client_data.status, Some(Status::default()));
+
+        assert_eq!(server_data.span_kind, SpanKind::Server);
+        assert_eq!(&server_data.span_context, server_span.span_context());
+        assert_eq!(
+            server_data.parent_span_id,
+            Some(client_data.span_context.span_id)
+        );
+        assert!(server_data.has_remote_parent);
+        assert_eq!(
+            server_data.status,
+            Some(Status {
+                code: StatusCode::NotFound,
+                message: "no such user".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn response_status() {
+        let te = Arc::new(TestExporter::default());
+        let dyn_te: Arc<dyn Exporter + Send + Sync> = te.clone();
+        register_exporter(Arc::clone(&dyn_te));
+
+        let call = |path: &str, headers: HeaderMap, message: Option<&'static [u8]>| {
+            let mut service = ServerLayer::default().layer(TestService {
+                requests: Arc::new(Mutex::new(Vec::new())),
+                headers,
+                message,
+                trailers: None,
+            });
+            let mut req = Request::post(path).body(()).unwrap();
+            inject_headers(&sampled_span_context(), req.headers_mut());
+            block_on(service.call(req)).unwrap().into_body()
+        };
+
+        // trailers-only responses are ended without reading the body.
+        drop(call(
+            "/test.Service/TrailersOnly",
+            status_trailers("7", "denied"),
+            None,
+        ));
+        drop(call(
+            "/test.Service/Dropped",
+            HeaderMap::new(),
+            Some(b"message"),
+        ));
+        read_body(call("/test.Service/NoStatus", HeaderMap::new(), None));
+
+        unregister_exporter(&dyn_te);
+
+        for (name, code, message) in &[
+            (
+                "Recv.test.Service.TrailersOnly",
+                StatusCode::PermissionDenied,
+                "denied",
+            ),
+            ("Recv.test.Service.Dropped", StatusCode::Cancelled, ""),
+            (
+                "Recv.test.Service.NoStatus",
+                StatusCode::Unknown,
+                "missing grpc-status",
+            ),
+        ] {
+            let got = exported_span(&te, name).unwrap();
+            assert_eq!(
+                got.status,
+                Some(Status {
+                    code: code.clone(),
+                    message: message.to_string(),
+                }),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::sync::Arc;
+
+use ::http::header::{HeaderMap, HeaderName, HeaderValue};
+use ::http::{Request, StatusCode};
+use io_context::Context;
+
+use crate::basetypes::{AttributeValue, Status};
+use crate::propagation::{Extractor, Injector, TextFormat};
+use crate::status_codes;
+use crate::trace::{
+    from_context, new_context, start_span_internal, Span, SpanKind, StartOption, StartOptions,
+};
+
+/// METHOD_ATTRIBUTE is the attribute key for the HTTP request method.
+pub const METHOD_ATTRIBUTE: &str = "http.method";
+/// PATH_ATTRIBUTE is the attribute key for the path of the request URL.
+pub const PATH_ATTRIBUTE: &str = "http.path";
+/// STATUS_CODE_ATTRIBUTE is the attribute key for the HTTP response status code.
+pub const STATUS_CODE_ATTRIBUTE: &str = "http.status_code";
+
+/// Header names are case insensitive, values that aren't valid header values
+/// are not set.
+impl Injector for HeaderMap {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.insert(name, value);
+        }
+    }
+}
+
+/// Header names are case insensitive, values that aren't visible ASCII are
+/// ignored.
+impl Extractor for HeaderMap {
+    fn get(&self, key: &str) -> Option<&str> {
+        HeaderMap::get(self, key)?.to_str().ok()
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        HeaderMap::keys(self).map(HeaderName::as_str).collect()
+    }
+}
+
+/// inject_request writes the span context of span into the headers of req
+/// using format.
+pub fn inject_request<B>(format: &dyn TextFormat, span: &Span, req: &mut Request<B>) {
+    format.inject(span.span_context(), req.headers_mut());
+}
+
+/// start_client_span starts a client span for an outgoing request and
+/// injects its span context into the request headers using format.
+///
+/// The span is named after the request path and records the method and path
+/// attributes. It is a child of the span in ctx, if any.
+pub fn start_client_span<B>(
+    ctx: &Arc<Context>,
+    format: &dyn TextFormat,
+    req: &mut Request<B>,
+    o: &[StartOption],
+) -> (Context, Span) {
+    let mut opts = StartOptions::from_options(o);
+    opts.span_kind = SpanKind::Client;
+
+    let parent = from_context(ctx).map(|p| p.span_context());
+    let mut span = start_span_internal(req.uri().path(), parent, false, &opts);
+    span.add_attributes(request_attributes(req));
+    inject_request(format, &span, req);
+
+    (new_context(ctx, span.clone()), span)
+}
+
+/// start_server_span starts a server span for an incoming request.
+///
+/// If format finds a span context in the request headers, the span is
+/// started with it as a remote parent, otherwise it is a child of the span
+/// in ctx, if any. The span is named after the request path and records the
+/// method and path attributes.
+pub fn start_server_span<B>(
+    ctx: &Arc<Context>,
+    format: &dyn TextFormat,
+    req: &Request<B>,
+    o: &[StartOption],
+) -> (Context, Span) {
+    let mut opts = StartOptions::from_options(o);
+    opts.span_kind = SpanKind::Server;
+
+    let name = req.uri().path();
+    let mut span = match format.extract(req.headers()) {
+        Some(remote_parent) => start_span_internal(name, Some(&remote_parent), true, &opts),
+        None => {
+            let parent = from_context(ctx).map(|p| p.span_context());
+            start_span_internal(name, parent, false, &opts)
+        }
+    };
+    span.add_attributes(request_attributes(req));
+
+    (new_context(ctx, span.clone()), span)
+}
+
+/// record_status_code records the status code of a response on span, and
+/// sets the span status from it following the OpenCensus HTTP specification.
+pub fn record_status_code(span: &mut Span, status: StatusCode) {
+    span.add_attributes(vec![(
+        STATUS_CODE_ATTRIBUTE.to_string(),
+        AttributeValue::Int64Attribute(i64::from(status.as_u16())),
+    )]);
+    span.set_status(&Status {
+        code: status_codes::StatusCode::from(status.as_u16()),
+        message: status.canonical_reason().unwrap_or_default().to_string(),
+    });
+}
+
+fn request_attributes<B>(req: &Request<B>) -> Vec<(String, AttributeValue)> {
+    vec![
+        (
+            METHOD_ATTRIBUTE.to_string(),
+            AttributeValue::StringAttribute(req.method().to_string()),
+        ),
+        (
+            PATH_ATTRIBUTE.to_string(),
+            AttributeValue::StringAttribute(req.uri().path().to_string()),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::basetypes::{SpanID, TraceID};
+    use crate::export::{register_exporter, unregister_exporter, Exporter};
+    use crate::propagation::b3::B3Format;
+    use crate::propagation::trace_context::TraceContextFormat;
+    use crate::sampling::always_sample;
+    use crate::test_util::TestExporter;
+    use crate::trace::{with_sampler, SpanContext, TraceOptions};
+
+    #[test]
+    fn header_map_carrier() {
+        let mut headers = HeaderMap::new();
+        Injector::set(&mut headers, "X-B3-TraceId", "abc".to_string());
+        Injector::set(&mut headers, "X-B3-SpanId", "bad\nvalue".to_string());
+
+        assert_eq!(Extractor::get(&headers, "x-b3-traceid"), Some("abc"));
+        assert_eq!(Extractor::get(&headers, "X-B3-TraceId"), Some("abc"));
+        assert_eq!(Extractor::get(&headers, "X-B3-SpanId"), None);
+    }
+
+    #[test]
+    fn client_span_propagates_to_server_span() {
+        let ctx = Context::background().freeze();
+        let mut req = Request::get("http://example.com/foo?bar=baz")
+            .body(())
+            .unwrap();
+
+        let (_, client_span) = start_client_span(
+            &ctx,
+            &B3Format::default(),
+            &mut req,
+            &[with_sampler(always_sample())],
+        );
+        assert!(req.headers().contains_key("x-b3-traceid"));
+
+        let (server_ctx, server_span) = start_server_span(&ctx, &B3Format::default(), &req, &[]);
+
+        let client_sc = client_span.span_context();
+        let server_sc = server_span.span_context();
+        assert_eq!(server_sc.trace_id, client_sc.trace_id);
+        assert!(server_sc.span_id != client_sc.span_id);
+        assert!(server_sc.is_sampled());
+        assert_eq!(from_context(&server_ctx).unwrap().span_context(), server_sc);
+    }
+
+    #[test]
+    fn server_span_records_request() {
+        let te = Arc::new(TestExporter::default());
+        let dyn_te: Arc<dyn Exporter + Send + Sync> = te.clone();
+
+        let sc = SpanContext {
+            trace_id: TraceID([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 4, 8, 16, 32, 64, 128]),
+            span_id: SpanID([1, 2, 4, 8, 16, 32, 64, 128]),
+            trace_options: TraceOptions(1),
+            trace_state: None,
+        };
+        let mut req = Request::post("/users/1").body(()).unwrap();
+        TraceContextFormat.inject(&sc, req.headers_mut());
+
+        let (_, mut span) = start_server_span(
+            &Context::background().freeze(),
+            &TraceContextFormat,
+            &req,
+            &[],
+        );
+        record_status_code(&mut span, StatusCode::NOT_FOUND);
+
+        register_exporter(Arc::clone(&dyn_te));
+        span.end();
+        unregister_exporter(&dyn_te);
+
+        let exported = te.exported_spans.lock().unwrap();
+        let got = exported
+            .iter()
+            .find(|s| s.span_context.trace_id == sc.trace_id)
+            .unwrap();
+        assert_eq!(got.name, "/users/1");
+        assert_eq!(got.span_kind, SpanKind::Server);
+        assert_eq!(got.parent_span_id, Some(sc.span_id));
+        assert!(got.has_remote_parent);
+        assert_eq!(
+            got.attributes.get(METHOD_ATTRIBUTE),
+            Some(&AttributeValue::StringAttribute("POST".to_string()))
+        );
+        assert_eq!(
+            got.attributes.get(PATH_ATTRIBUTE),
+            Some(&AttributeValue::StringAttribute("/users/1".to_string()))
+        );
+        assert_eq!(
+            got.attributes.get(STATUS_CODE_ATTRIBUTE),
+            Some(&AttributeValue::Int64Attribute(404))
+        );
+        assert_eq!(
+            got.status,
+            Some(Status {
+                code: status_codes::StatusCode::NotFound,
+                message: "Not Found".to_string(),
+            })
+        );
+    }
+}
@@ -49,28 +49,75 @@ let (ctx, span) = start_span(&parent.freeze(), "example.com/Run", &[]);
 
 start_span will create a new top-level span if the context
 doesn't contain another span, otherwise it will create a child span.
+
+Code that holds the parent span, or only its SpanContext, can start a child
+without a Context:
+
+```rust
+use opencensus_trace::{start_child_span, start_span};
+
+let (_, parent) = start_span(&io_context::Context::background().freeze(), "parent", &[]);
+let child = parent.start_child("child", &[]);
+let grandchild = start_child_span("grandchild", child.span_context(), &[]);
+```
+
+The span must be ended with span.end() for it to be exported. To end it
+whenever the function returns, including early returns and panics, use
+start_span_guarded, which returns a SpanGuard that ends the span on drop:
+
+```rust
+use opencensus_trace::start_span_guarded;
+
+let parent = io_context::Context::background();
+
+let (ctx, mut span) = start_span_guarded(&parent.freeze(), "example.com/Run", &[]);
+span.annotate("started");
+```
 */
 #![warn(missing_docs, rust_2018_idioms, clippy::all)]
 
 mod basetypes;
 mod config;
 mod export;
+/// gRPC integration
+#[cfg(feature = "grpc")]
+pub mod grpc;
+/// HTTP integration
+#[cfg(feature = "http")]
+pub mod http;
 mod id_generator;
 /// Trace propagation
 pub mod propagation;
 /// Trace sampling
 pub mod sampling;
+#[allow(dead_code)]
 mod spanbucket;
+#[allow(dead_code)]
 mod spanstore;
 mod status_codes;
+/// Test helpers shared by the tests of this crate and of exporters
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
 mod trace;
-mod tracestate;
+/// Trace state
+pub mod tracestate;
 
-pub use crate::basetypes::{Annotation, AttributeValue, Attributes, SpanID, TraceID};
-pub use crate::config::{set_global_default_sampler, set_global_id_generator, Config};
-pub use crate::export::{register_exporter, unregister_exporter};
+pub use crate::basetypes::{
+    Annotation, AttributeValue, Attributes, Link, LinkType, MessageEvent, MessageEventType, SpanID,
+    Status, TraceID,
+};
+pub use crate::config::{
+    set_global_default_sampler, set_global_id_generator, set_global_span_limits, Config, SpanLimits,
+};
+pub use crate::export::{
+    epoch_micros, epoch_nanos, register_exporter, shutdown_exporters, unregister_exporter,
+    BatchConfig, BatchExporter, ExportError, Exporter, SpanData, SpanExporter,
+    DEFAULT_FLUSH_INTERVAL, DEFAULT_MAX_BATCH_SIZE, DEFAULT_MAX_QUEUE_SIZE,
+};
 pub use crate::sampling::{always_sample, never_sample};
+pub use crate::status_codes::{InvalidStatusCode, ParseStatusCodeError, StatusCode};
 pub use crate::trace::{
-    start_span, start_span_with_remote_parent, with_sampler, with_span_kind, Span, SpanContext,
-    TraceOptions,
+    start_child_span, start_consumer_span, start_producer_span, start_span, start_span_guarded,
+    start_span_with_remote_parent, with_sampler, with_span_kind, with_start_time, Span,
+    SpanContext, SpanGuard, SpanKind, TraceOptions,
 };
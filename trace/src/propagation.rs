@@ -1,6 +1,20 @@
+use std::collections::HashMap;
+use std::fmt;
+
 use crate::basetypes::{SpanID, TraceID};
 use crate::trace::{SpanContext, TraceOptions};
 
+/// B3 propagation
+pub mod b3;
+/// Google Cloud Trace propagation
+pub mod cloud_trace;
+/// Jaeger propagation
+pub mod jaeger;
+/// W3C Trace Context propagation
+pub mod trace_context;
+/// AWS X-Ray propagation
+pub mod xray;
+
 /// BinaryFormat format:
 ///
 /// Binary value: <version_id><version_format>
@@ -19,6 +33,10 @@ use crate::trace::{SpanContext, TraceOptions};
 ///
 /// Fields MUST be encoded using the field id order (smaller to higher).
 ///
+/// Only the TraceId is required. Decoders stop at the first field id they
+/// don't know, as its length can't be known, and parse versions greater than
+/// 0 with the version 0 fields.
+///
 /// Valid value example:
 ///
 /// {0, 0, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 1, 97,
@@ -28,11 +46,10 @@ use crate::trace::{SpanContext, TraceOptions};
 /// trace_id = {64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79}
 /// span_id = {97, 98, 99, 100, 101, 102, 103, 104};
 /// trace_options = {1};
-
+///
 /// to_binary returns the binary format representation of a SpanContext.
 pub fn to_binary(sc: &SpanContext) -> Vec<u8> {
-    let mut buf: Vec<u8> = Vec::new();
-    buf.resize(29, 0);
+    let mut buf: Vec<u8> = vec![0; 29];
     buf[2..18].copy_from_slice(&sc.trace_id.0);
     buf[18] = 1;
     buf[19..27].copy_from_slice(&sc.span_id.0);
@@ -41,72 +58,218 @@ pub fn to_binary(sc: &SpanContext) -> Vec<u8> {
     buf
 }
 
-/// from_binary returns the SpanContext represented by b.
-///
-/// If b has an unsupported version ID or contains no TraceID, FromBinary
-/// returns with None.
-pub fn from_binary(buf: &[u8]) -> Option<SpanContext> {
-    let mut b = buf;
-    if b.is_empty() || b[0] != 0 {
-        return None;
-    }
+/// BinaryFormatError is returned when a SpanContext cannot be decoded from
+/// the binary format.
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum BinaryFormatError {
+    /// The value is empty so has no version ID.
+    MissingVersion,
+    /// The value doesn't contain a TraceId field.
+    MissingTraceID,
+    /// The value ends before the end of the field with the given field id.
+    TruncatedField(u8),
+}
 
-    b = &b[1..];
-    let trace_id;
-    if b.len() >= 17 && b[0] == 0 {
-        let mut a: [u8; 16] = Default::default();
-        a.copy_from_slice(&b[1..17]);
-        trace_id = TraceID(a);
-    } else {
-        return None;
+impl fmt::Display for BinaryFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            BinaryFormatError::MissingVersion => write!(f, "binary value has no version id"),
+            BinaryFormatError::MissingTraceID => write!(f, "binary value has no trace id"),
+            BinaryFormatError::TruncatedField(field_id) => {
+                write!(f, "binary value ends within field {}", field_id)
+            }
+        }
     }
+}
 
-    b = &b[17..];
-    let span_id;
-    if b.len() >= 9 && b[0] == 1 {
-        let mut a: [u8; 8] = Default::default();
-        a.copy_from_slice(&b[1..9]);
-        span_id = SpanID(a);
-    } else {
-        return None;
+impl std::error::Error for BinaryFormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
     }
+}
 
-    b = &b[9..];
-    let trace_options;
-    if b.len() >= 2 && b[0] == 2 {
-        trace_options = TraceOptions(u32::from(b[1]));
-    } else {
-        return None;
+const TRACE_ID_FIELD: u8 = 0;
+const SPAN_ID_FIELD: u8 = 1;
+const TRACE_OPTIONS_FIELD: u8 = 2;
+
+/// from_binary returns the SpanContext represented by buf.
+///
+/// Missing SpanId and TraceOptions fields take their default values. If buf
+/// is empty, contains no TraceId or ends part way through a field,
+/// from_binary returns the reason as an error.
+pub fn from_binary(buf: &[u8]) -> Result<SpanContext, BinaryFormatError> {
+    // every version is parsed with the version 0 fields.
+    let mut b = buf.get(1..).ok_or(BinaryFormatError::MissingVersion)?;
+
+    let mut trace_id = None;
+    let mut span_id = SpanID::default();
+    let mut trace_options = TraceOptions::default();
+
+    let mut next_field_id = TRACE_ID_FIELD;
+    while let Some(&field_id) = b.first() {
+        let len = match field_id {
+            TRACE_ID_FIELD => 16,
+            SPAN_ID_FIELD => 8,
+            TRACE_OPTIONS_FIELD => 1,
+            _ => break,
+        };
+        // fields out of order can't belong to a version we understand.
+        if field_id < next_field_id {
+            break;
+        }
+        let value = b
+            .get(1..=len)
+            .ok_or(BinaryFormatError::TruncatedField(field_id))?;
+        match field_id {
+            TRACE_ID_FIELD => {
+                let mut a: [u8; 16] = Default::default();
+                a.copy_from_slice(value);
+                trace_id = Some(TraceID(a));
+            }
+            SPAN_ID_FIELD => span_id.0.copy_from_slice(value),
+            _ => trace_options = TraceOptions(u32::from(value[0])),
+        }
+        next_field_id = field_id + 1;
+        b = &b[1 + len..];
     }
 
-    Some(SpanContext {
-        trace_id,
+    Ok(SpanContext {
+        trace_id: trace_id.ok_or(BinaryFormatError::MissingTraceID)?,
         span_id,
         trace_options,
         trace_state: None,
     })
 }
 
-// TODO(john|p=2|#feature|#http): Support Http format, hyper feature flag?
-/*
-/// HTTPFormat implementations propagate span contexts
-/// in HTTP requests.
-///
-/// SpanContextFromRequest extracts a span context from incoming
-/// requests.
+/// decode_hex fills out with the bytes represented by s, which must be
+/// exactly twice as long as out and contain only lowercase hex digits.
+fn decode_hex(s: &str, out: &mut [u8]) -> Option<()> {
+    if s.len() != out.len() * 2 {
+        return None;
+    }
+    for (i, pair) in s.as_bytes().chunks(2).enumerate() {
+        out[i] = (hex_digit(pair[0])? << 4) | hex_digit(pair[1])?;
+    }
+    Some(())
+}
+
+fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        _ => None,
+    }
+}
+
+/// TextFormat implementations propagate span contexts through carriers
+/// that map string keys to string values, such as HTTP headers or message
+/// properties.
 ///
-/// SpanContextToRequest modifies the given request to include the given
-/// span context.
-trait HTTPFormat<Request> {
-    pub fn span_context_from_request(req: &Request) -> (sc: SpanContext, ok: bool)
-    pub fn span_context_to_request(sc: SpanContext, req: &mut Request)
+/// TextFormat is object safe so the format can be chosen at runtime.
+pub trait TextFormat {
+    /// fields returns the keys the format reads and writes.
+    fn fields(&self) -> &[&'static str];
+
+    /// inject writes the given span context into the carrier.
+    fn inject(&self, sc: &SpanContext, carrier: &mut dyn Injector);
+
+    /// extract reads a span context from the carrier, returning None if the
+    /// carrier doesn't contain a valid one.
+    fn extract(&self, carrier: &dyn Extractor) -> Option<SpanContext>;
+}
+
+/// Injector is a carrier a TextFormat can write to.
+pub trait Injector {
+    /// set stores the value for key, replacing any existing value.
+    fn set(&mut self, key: &str, value: String);
+}
+
+/// Extractor is a carrier a TextFormat can read from.
+pub trait Extractor {
+    /// get returns the value for key, if any.
+    fn get(&self, key: &str) -> Option<&str>;
+
+    /// keys returns all the keys in the carrier. It is only needed by formats
+    /// that look keys up by prefix, such as Jaeger baggage, and returns no
+    /// keys by default.
+    fn keys(&self) -> Vec<&str> {
+        Vec::new()
+    }
+}
+
+impl Injector for HashMap<String, String> {
+    fn set(&mut self, key: &str, value: String) {
+        self.insert(key.to_string(), value);
+    }
+}
+
+/// Keys are matched exactly first and then ignoring ASCII case, as header
+/// names are often normalized before they reach the map.
+impl Extractor for HashMap<String, String> {
+    fn get(&self, key: &str) -> Option<&str> {
+        HashMap::get(self, key)
+            .or_else(|| {
+                self.iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                    .map(|(_, v)| v)
+            })
+            .map(String::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        HashMap::keys(self).map(String::as_str).collect()
+    }
 }
-*/
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use crate::propagation::b3::B3Format;
+    use crate::propagation::trace_context::TraceContextFormat;
+
+    #[test]
+    fn text_format_roundtrip() {
+        let sc = SpanContext {
+            trace_id: TraceID([
+                0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x4b, 0x4c, 0x4d,
+                0x4e, 0x4f,
+            ]),
+            span_id: SpanID([0x61, 0x62, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68]),
+            trace_options: TraceOptions(1),
+            trace_state: None,
+        };
+
+        let formats: Vec<Box<dyn TextFormat>> = vec![
+            Box::new(TraceContextFormat),
+            Box::new(B3Format::default()),
+            Box::new(B3Format {
+                single_header: true,
+            }),
+        ];
+
+        for format in formats {
+            let mut carrier = HashMap::new();
+            format.inject(&sc, &mut carrier);
+            for key in carrier.keys() {
+                assert!(format.fields().contains(&key.as_str()));
+            }
+            assert_eq!(format.extract(&carrier), Some(sc.clone()));
+        }
+    }
+
+    #[test]
+    fn hash_map_extractor_ignores_case() {
+        let mut carrier = HashMap::new();
+        carrier.insert("x-b3-traceid".to_string(), "value".to_string());
+        carrier.insert("X-B3-TraceId".to_string(), "exact".to_string());
+        carrier.insert("X-B3-SPANID".to_string(), "other".to_string());
+
+        assert_eq!(Extractor::get(&carrier, "X-B3-TraceId"), Some("exact"));
+        assert_eq!(Extractor::get(&carrier, "x-b3-spanid"), Some("other"));
+        assert_eq!(Extractor::get(&carrier, "X-B3-Sampled"), None);
+    }
+
     #[test]
     fn test_to_binary() {
         let trace_id = TraceID([
@@ -128,23 +291,21 @@ mod tests {
 
         assert_eq!(*b2, *b);
 
-        match from_binary(&mut b.clone()) {
-            None => panic!("decode failed"),
-            Some(span_context) => {
+        match from_binary(&b) {
+            Err(e) => panic!("decode failed: {}", e),
+            Ok(span_context) => {
                 assert_eq!(span_context.trace_id, trace_id);
                 assert_eq!(span_context.span_id, span_id);
             }
         }
 
         b[0] = 1;
-        if from_binary(&mut b).is_some() {
-            panic!("decoded bytes containing unsupported version");
+        if let Err(e) = from_binary(&b) {
+            panic!("failed to decode bytes containing a later version: {}", e);
         }
 
         b = vec![0, 1, 97, 98, 99, 100, 101, 102, 103, 104, 2, 1];
-        if from_binary(&mut b).is_some() {
-            panic!("decoded bytes without a TraceID");
-        }
+        assert_eq!(from_binary(&b), Err(BinaryFormatError::MissingTraceID));
 
         // No such thing as an empty struct in Rust so can't replicate Go tests
     }
@@ -156,13 +317,18 @@ mod tests {
             100, 101, 102, 103, 104, 2, 1,
         ];
 
+        let trace_id = TraceID([
+            64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79,
+        ]);
+        let span_id = SpanID([97, 98, 99, 100, 101, 102, 103, 104]);
+
         #[derive(Clone)]
         struct TestCase<'a> {
             data: &'a [u8],
             want_trace_id: Option<TraceID>,
             want_span_id: Option<SpanID>,
             want_opts: Option<TraceOptions>,
-            want_ok: bool,
+            want_err: Option<BinaryFormatError>,
         }
 
         let mut test_cases = [
@@ -171,31 +337,99 @@ mod tests {
                 want_trace_id: None,
                 want_span_id: None,
                 want_opts: None,
-                want_ok: false,
+                want_err: Some(BinaryFormatError::TruncatedField(0)),
             },
             TestCase {
                 data: &[0, 1, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77],
                 want_trace_id: None,
                 want_span_id: None,
                 want_opts: None,
-                want_ok: false,
+                want_err: Some(BinaryFormatError::MissingTraceID),
+            },
+            TestCase {
+                data: &[],
+                want_trace_id: None,
+                want_span_id: None,
+                want_opts: None,
+                want_err: Some(BinaryFormatError::MissingVersion),
             },
             TestCase {
                 data: &valid_data,
-                want_trace_id: Some(TraceID([
-                    64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79,
-                ])),
-                want_span_id: Some(SpanID([97, 98, 99, 100, 101, 102, 103, 104])),
+                want_trace_id: Some(trace_id),
+                want_span_id: Some(span_id),
+                want_opts: Some(TraceOptions(1)),
+                want_err: None,
+            },
+            // span id and trace options are optional.
+            TestCase {
+                data: &valid_data[..18],
+                want_trace_id: Some(trace_id),
+                want_span_id: Some(SpanID::default()),
+                want_opts: Some(TraceOptions(0)),
+                want_err: None,
+            },
+            TestCase {
+                data: &[
+                    0, 0, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 2, 1,
+                ],
+                want_trace_id: Some(trace_id),
+                want_span_id: Some(SpanID::default()),
+                want_opts: Some(TraceOptions(1)),
+                want_err: None,
+            },
+            TestCase {
+                data: &valid_data[..27],
+                want_trace_id: Some(trace_id),
+                want_span_id: Some(span_id),
+                want_opts: Some(TraceOptions(0)),
+                want_err: None,
+            },
+            TestCase {
+                data: &valid_data[..28],
+                want_trace_id: None,
+                want_span_id: None,
+                want_opts: None,
+                want_err: Some(BinaryFormatError::TruncatedField(2)),
+            },
+            // unknown fields and later versions are parsed leniently.
+            TestCase {
+                data: &[
+                    0, 0, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 1, 97,
+                    98, 99, 100, 101, 102, 103, 104, 3, 5, 2, 1,
+                ],
+                want_trace_id: Some(trace_id),
+                want_span_id: Some(span_id),
+                want_opts: Some(TraceOptions(0)),
+                want_err: None,
+            },
+            TestCase {
+                data: &[
+                    7, 0, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 1, 97,
+                    98, 99, 100, 101, 102, 103, 104, 2, 1, 9, 1, 2, 3,
+                ],
+                want_trace_id: Some(trace_id),
+                want_span_id: Some(span_id),
                 want_opts: Some(TraceOptions(1)),
-                want_ok: true,
+                want_err: None,
+            },
+            TestCase {
+                data: &[
+                    0, 0, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 1, 97,
+                    98, 99, 100, 101, 102, 103, 104, 0, 1,
+                ],
+                want_trace_id: Some(trace_id),
+                want_span_id: Some(span_id),
+                want_opts: Some(TraceOptions(0)),
+                want_err: None,
             },
         ];
 
         for test_case in test_cases.iter_mut() {
-            let mut data = test_case.data.to_vec();
-            match from_binary(&mut data) {
-                None => assert!(!test_case.want_ok, "unexpected error while decoding"),
-                Some(span_context) => {
+            let data = test_case.data.to_vec();
+            match from_binary(&data) {
+                Err(e) => assert_eq!(Some(e), test_case.want_err, "{:?}", test_case.data),
+                Ok(span_context) => {
+                    assert!(test_case.want_err.is_none(), "{:?}", test_case.data);
                     if let Some(trace_id) = test_case.want_trace_id {
                         assert_eq!(span_context.trace_id, trace_id);
                     }
@@ -0,0 +1,428 @@
+use crate::basetypes::{SpanID, TraceID};
+use crate::propagation::{decode_hex, Extractor, Injector, TextFormat};
+use crate::trace::{SpanContext, TraceOptions, DEBUG_FLAG, SAMPLED_FLAG};
+
+/// TRACE_ID_HEADER is the header carrying the 64 or 128-bit trace ID.
+pub const TRACE_ID_HEADER: &str = "X-B3-TraceId";
+/// SPAN_ID_HEADER is the header carrying the 64-bit span ID.
+pub const SPAN_ID_HEADER: &str = "X-B3-SpanId";
+/// SAMPLED_HEADER is the header carrying the sampling decision.
+pub const SAMPLED_HEADER: &str = "X-B3-Sampled";
+/// FLAGS_HEADER is the header carrying the debug flag.
+pub const FLAGS_HEADER: &str = "X-B3-Flags";
+/// SINGLE_HEADER is the header carrying the single header encoding.
+pub const SINGLE_HEADER: &str = "b3";
+
+/// B3 multiple header format:
+///
+/// X-B3-TraceId: 32 or 16 lowercase hex characters, 64-bit IDs are padded
+/// with zeroes on the left.
+/// X-B3-SpanId: 16 lowercase hex characters.
+/// X-B3-Sampled: "1" or "true" to accept, "0" or "false" to deny sampling.
+/// X-B3-Flags: "1" marks the trace for debugging, which implies sampling.
+///
+/// A debug trace only sends X-B3-Flags as it already implies acceptance.
+///
+/// to_b3_headers returns the B3 headers and values for a SpanContext.
+pub fn to_b3_headers(sc: &SpanContext) -> Vec<(&'static str, String)> {
+    let mut headers = vec![
+        (TRACE_ID_HEADER, sc.trace_id.to_string()),
+        (SPAN_ID_HEADER, sc.span_id.to_string()),
+    ];
+    if sc.trace_options.is_debug() {
+        headers.push((FLAGS_HEADER, "1".to_string()));
+    } else if sc.is_sampled() {
+        headers.push((SAMPLED_HEADER, "1".to_string()));
+    } else {
+        headers.push((SAMPLED_HEADER, "0".to_string()));
+    }
+    headers
+}
+
+/// from_b3_headers returns the SpanContext represented by the B3 headers
+/// returned by get.
+///
+/// If the trace or span ID is missing or invalid, from_b3_headers returns
+/// with None. A missing sampling decision is treated as a denial.
+pub fn from_b3_headers<'a>(get: impl Fn(&str) -> Option<&'a str>) -> Option<SpanContext> {
+    let trace_id = parse_trace_id(get(TRACE_ID_HEADER)?)?;
+    let span_id = parse_span_id(get(SPAN_ID_HEADER)?)?;
+
+    let mut trace_options = TraceOptions::default();
+    if let Some(sampled) = get(SAMPLED_HEADER) {
+        trace_options = match sampled.trim() {
+            "1" | "true" => TraceOptions(SAMPLED_FLAG),
+            "0" | "false" => TraceOptions(0),
+            _ => return None,
+        };
+    }
+    if let Some("1") = get(FLAGS_HEADER).map(str::trim) {
+        trace_options = TraceOptions(SAMPLED_FLAG | DEBUG_FLAG);
+    }
+
+    Some(SpanContext {
+        trace_id,
+        span_id,
+        trace_options,
+        trace_state: None,
+    })
+}
+
+/// B3 single header format:
+///
+/// Header value: <trace-id>-<span-id>[-<sampling-state>[-<parent-span-id>]]
+///
+/// sampling-state: "1" to accept, "0" to deny sampling, "d" for debug.
+///
+/// The IDs are encoded as in the multiple header format. A header holding
+/// only a sampling state carries no SpanContext.
+///
+/// Valid value example:
+///
+/// 80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1-05e3ac9a4f6e3b90
+///
+/// to_b3_single_header returns the b3 header value for a SpanContext.
+pub fn to_b3_single_header(sc: &SpanContext) -> String {
+    let sampling_state = if sc.trace_options.is_debug() {
+        "d"
+    } else if sc.is_sampled() {
+        "1"
+    } else {
+        "0"
+    };
+    format!("{}-{}-{}", sc.trace_id, sc.span_id, sampling_state)
+}
+
+/// from_b3_single_header returns the SpanContext represented by a b3 header
+/// value.
+///
+/// If the value is malformed or only holds a sampling state,
+/// from_b3_single_header returns with None.
+pub fn from_b3_single_header(value: &str) -> Option<SpanContext> {
+    let mut fields = value.trim().split('-');
+
+    let trace_id = parse_trace_id(fields.next()?)?;
+    let span_id = parse_span_id(fields.next()?)?;
+    let trace_options = match fields.next() {
+        None | Some("0") => TraceOptions(0),
+        Some("1") => TraceOptions(SAMPLED_FLAG),
+        Some("d") => TraceOptions(SAMPLED_FLAG | DEBUG_FLAG),
+        Some(_) => return None,
+    };
+    if let Some(parent_span_id) = fields.next() {
+        parse_span_id(parent_span_id)?;
+    }
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(SpanContext {
+        trace_id,
+        span_id,
+        trace_options,
+        trace_state: None,
+    })
+}
+
+/// B3Format propagates span contexts in B3 headers.
+///
+/// Both encodings are accepted when extracting, with the single header taking
+/// precedence. single_header selects the encoding used when injecting.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct B3Format {
+    /// single_header makes inject write the b3 header instead of the
+    /// X-B3-* headers.
+    pub single_header: bool,
+}
+
+impl TextFormat for B3Format {
+    /// fields lists the headers of both encodings, as extract reads them
+    /// whichever is used to inject.
+    fn fields(&self) -> &[&'static str] {
+        &[
+            SINGLE_HEADER,
+            TRACE_ID_HEADER,
+            SPAN_ID_HEADER,
+            SAMPLED_HEADER,
+            FLAGS_HEADER,
+        ]
+    }
+
+    fn inject(&self, sc: &SpanContext, carrier: &mut dyn Injector) {
+        if self.single_header {
+            carrier.set(SINGLE_HEADER, to_b3_single_header(sc));
+        } else {
+            for (key, value) in to_b3_headers(sc) {
+                carrier.set(key, value);
+            }
+        }
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Option<SpanContext> {
+        match carrier.get(SINGLE_HEADER) {
+            Some(value) => from_b3_single_header(value),
+            None => from_b3_headers(|key| carrier.get(key)),
+        }
+    }
+}
+
+fn parse_trace_id(s: &str) -> Option<TraceID> {
+    let s = s.trim().to_ascii_lowercase();
+    let mut trace_id = TraceID::default();
+    match s.len() {
+        32 => decode_hex(&s, &mut trace_id.0)?,
+        16 => decode_hex(&s, &mut trace_id.0[8..])?,
+        _ => return None,
+    }
+    if trace_id == TraceID::default() {
+        return None;
+    }
+    Some(trace_id)
+}
+
+fn parse_span_id(s: &str) -> Option<SpanID> {
+    let s = s.trim().to_ascii_lowercase();
+    let mut span_id = SpanID::default();
+    decode_hex(&s, &mut span_id.0)?;
+    if span_id == SpanID::default() {
+        return None;
+    }
+    Some(span_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    const TID: TraceID = TraceID([
+        0x80, 0xf1, 0x98, 0xee, 0x56, 0x34, 0x3b, 0xa8, 0x64, 0xfe, 0x8b, 0x2a, 0x57, 0xd3, 0xef,
+        0xf7,
+    ]);
+    const SHORT_TID: TraceID = TraceID([
+        0, 0, 0, 0, 0, 0, 0, 0, 0x64, 0xfe, 0x8b, 0x2a, 0x57, 0xd3, 0xef, 0xf7,
+    ]);
+    const SID: SpanID = SpanID([0xe4, 0x57, 0xb5, 0xa2, 0xe4, 0xd8, 0x6b, 0xd1]);
+
+    #[test]
+    fn test_from_b3_headers() {
+        struct TestCase {
+            headers: Vec<(&'static str, &'static str)>,
+            want: Option<SpanContext>,
+        }
+
+        let sc = |trace_id, trace_options| {
+            Some(SpanContext {
+                trace_id,
+                span_id: SID,
+                trace_options: TraceOptions(trace_options),
+                trace_state: None,
+            })
+        };
+
+        let test_cases = vec![
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "80f198ee56343ba864fe8b2a57d3eff7"),
+                    (SPAN_ID_HEADER, "e457b5a2e4d86bd1"),
+                    (SAMPLED_HEADER, "1"),
+                ],
+                want: sc(TID, SAMPLED_FLAG),
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "80F198EE56343BA864FE8B2A57D3EFF7"),
+                    (SPAN_ID_HEADER, "E457B5A2E4D86BD1"),
+                    (SAMPLED_HEADER, "true"),
+                ],
+                want: sc(TID, SAMPLED_FLAG),
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "64fe8b2a57d3eff7"),
+                    (SPAN_ID_HEADER, "e457b5a2e4d86bd1"),
+                    (SAMPLED_HEADER, "1"),
+                ],
+                want: sc(SHORT_TID, SAMPLED_FLAG),
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "80f198ee56343ba864fe8b2a57d3eff7"),
+                    (SPAN_ID_HEADER, "e457b5a2e4d86bd1"),
+                    (SAMPLED_HEADER, "0"),
+                ],
+                want: sc(TID, 0),
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "80f198ee56343ba864fe8b2a57d3eff7"),
+                    (SPAN_ID_HEADER, "e457b5a2e4d86bd1"),
+                ],
+                want: sc(TID, 0),
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "80f198ee56343ba864fe8b2a57d3eff7"),
+                    (SPAN_ID_HEADER, "e457b5a2e4d86bd1"),
+                    (FLAGS_HEADER, "1"),
+                ],
+                want: sc(TID, SAMPLED_FLAG | DEBUG_FLAG),
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "80f198ee56343ba864fe8b2a57d3eff7"),
+                    (SPAN_ID_HEADER, "e457b5a2e4d86bd1"),
+                    (SAMPLED_HEADER, "yes"),
+                ],
+                want: None,
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "80f198ee56343ba864fe8b2a57d3ef"),
+                    (SPAN_ID_HEADER, "e457b5a2e4d86bd1"),
+                ],
+                want: None,
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "00000000000000000000000000000000"),
+                    (SPAN_ID_HEADER, "e457b5a2e4d86bd1"),
+                ],
+                want: None,
+            },
+            TestCase {
+                headers: vec![
+                    (TRACE_ID_HEADER, "80f198ee56343ba864fe8b2a57d3eff7"),
+                    (SPAN_ID_HEADER, "0000000000000000"),
+                ],
+                want: None,
+            },
+            TestCase {
+                headers: vec![(SPAN_ID_HEADER, "e457b5a2e4d86bd1")],
+                want: None,
+            },
+        ];
+
+        for test_case in test_cases {
+            let headers: HashMap<_, _> = test_case.headers.iter().cloned().collect();
+            let got = from_b3_headers(|k| headers.get(k).cloned());
+            assert_eq!(got, test_case.want, "{:?}", test_case.headers);
+        }
+    }
+
+    #[test]
+    fn b3_headers_roundtrip() {
+        for trace_options in &[0, SAMPLED_FLAG, SAMPLED_FLAG | DEBUG_FLAG] {
+            let sc = SpanContext {
+                trace_id: TID,
+                span_id: SID,
+                trace_options: TraceOptions(*trace_options),
+                trace_state: None,
+            };
+            let headers: HashMap<_, _> = to_b3_headers(&sc).into_iter().collect();
+            assert_eq!(headers.len(), 3);
+            assert_eq!(
+                from_b3_headers(|k| headers.get(k).map(String::as_str)),
+                Some(sc)
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_b3_single_header() {
+        let test_cases = vec![
+            (
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1-05e3ac9a4f6e3b90",
+                Some((TID, SAMPLED_FLAG)),
+            ),
+            (
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1",
+                Some((TID, SAMPLED_FLAG)),
+            ),
+            (
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-d",
+                Some((TID, SAMPLED_FLAG | DEBUG_FLAG)),
+            ),
+            (
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0",
+                Some((TID, 0)),
+            ),
+            (
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1",
+                Some((TID, 0)),
+            ),
+            (
+                "64fe8b2a57d3eff7-e457b5a2e4d86bd1-1",
+                Some((SHORT_TID, SAMPLED_FLAG)),
+            ),
+            ("0", None),
+            ("1", None),
+            ("80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-2", None),
+            (
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1-0",
+                None,
+            ),
+            (
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1-05e3ac9a4f6e3b90-1",
+                None,
+            ),
+            ("80f198ee56343ba864fe8b2a57d3eff7--1", None),
+        ];
+
+        for (value, want) in test_cases {
+            let want = want.map(|(trace_id, trace_options)| SpanContext {
+                trace_id,
+                span_id: SID,
+                trace_options: TraceOptions(trace_options),
+                trace_state: None,
+            });
+            assert_eq!(from_b3_single_header(value), want, "{}", value);
+        }
+    }
+
+    #[test]
+    fn b3_single_header_roundtrip() {
+        for (trace_options, want) in &[
+            (0, "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0"),
+            (
+                SAMPLED_FLAG,
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-1",
+            ),
+            (
+                SAMPLED_FLAG | DEBUG_FLAG,
+                "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-d",
+            ),
+        ] {
+            let sc = SpanContext {
+                trace_id: TID,
+                span_id: SID,
+                trace_options: TraceOptions(*trace_options),
+                trace_state: None,
+            };
+            let value = to_b3_single_header(&sc);
+            assert_eq!(&value, want);
+            assert_eq!(from_b3_single_header(&value), Some(sc));
+        }
+    }
+
+    #[test]
+    fn fields_include_both_encodings() {
+        for format in &[
+            B3Format::default(),
+            B3Format {
+                single_header: true,
+            },
+        ] {
+            for key in &[
+                SINGLE_HEADER,
+                TRACE_ID_HEADER,
+                SPAN_ID_HEADER,
+                SAMPLED_HEADER,
+                FLAGS_HEADER,
+            ] {
+                assert!(format.fields().contains(key), "{:?} {}", format, key);
+            }
+        }
+    }
+}
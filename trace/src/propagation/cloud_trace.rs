@@ -0,0 +1,162 @@
+use crate::basetypes::{SpanID, TraceID};
+use crate::propagation::{decode_hex, Extractor, Injector, TextFormat};
+use crate::trace::{SpanContext, TraceOptions, SAMPLED_FLAG};
+
+/// TRACE_CONTEXT_HEADER is the header carrying the Cloud Trace context.
+pub const TRACE_CONTEXT_HEADER: &str = "X-Cloud-Trace-Context";
+
+// Header values longer than this are ignored, as in the Go implementation.
+const MAX_HEADER_LEN: usize = 200;
+
+/// Cloud Trace context format:
+///
+/// Header value: <trace-id>/<span-id>[;o=<options>]
+///
+/// trace-id: 32 hex characters.
+/// span-id: the 64-bit span ID as an unsigned decimal number.
+/// options: "1" if the trace is sampled, "0" otherwise. A missing option is
+/// treated as a denial.
+///
+/// Valid value example:
+///
+/// 105445aa7843bc8bf206b12000100000/1;o=1
+///
+/// to_cloud_trace_header returns the X-Cloud-Trace-Context header value for
+/// a SpanContext.
+pub fn to_cloud_trace_header(sc: &SpanContext) -> String {
+    format!(
+        "{}/{};o={}",
+        sc.trace_id,
+        u64::from_be_bytes(sc.span_id.0),
+        if sc.is_sampled() { 1 } else { 0 },
+    )
+}
+
+/// from_cloud_trace_header returns the SpanContext represented by an
+/// X-Cloud-Trace-Context header value.
+///
+/// If the value is malformed or contains an all zero trace or span ID,
+/// from_cloud_trace_header returns with None.
+pub fn from_cloud_trace_header(value: &str) -> Option<SpanContext> {
+    let value = value.trim();
+    if value.len() > MAX_HEADER_LEN {
+        return None;
+    }
+
+    let (trace_id, rest) = value.split_once('/')?;
+    let (span_id, options) = match rest.split_once(';') {
+        Some((span_id, options)) => (span_id, Some(options)),
+        None => (rest, None),
+    };
+
+    let mut tid = TraceID::default();
+    decode_hex(&trace_id.to_ascii_lowercase(), &mut tid.0)?;
+    let sid = SpanID(span_id.parse::<u64>().ok()?.to_be_bytes());
+    if tid == TraceID::default() || sid == SpanID::default() {
+        return None;
+    }
+
+    let mut trace_options = TraceOptions::default();
+    if let Some(options) = options {
+        let o: u64 = options.strip_prefix("o=")?.parse().ok()?;
+        if o & u64::from(SAMPLED_FLAG) != 0 {
+            trace_options = TraceOptions(SAMPLED_FLAG);
+        }
+    }
+
+    Some(SpanContext {
+        trace_id: tid,
+        span_id: sid,
+        trace_options,
+        trace_state: None,
+    })
+}
+
+/// CloudTraceFormat propagates span contexts in the X-Cloud-Trace-Context
+/// header.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CloudTraceFormat;
+
+impl TextFormat for CloudTraceFormat {
+    fn fields(&self) -> &[&'static str] {
+        &[TRACE_CONTEXT_HEADER]
+    }
+
+    fn inject(&self, sc: &SpanContext, carrier: &mut dyn Injector) {
+        carrier.set(TRACE_CONTEXT_HEADER, to_cloud_trace_header(sc));
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Option<SpanContext> {
+        from_cloud_trace_header(carrier.get(TRACE_CONTEXT_HEADER)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    const TID: TraceID = TraceID([
+        0x10, 0x54, 0x45, 0xaa, 0x78, 0x43, 0xbc, 0x8b, 0xf2, 0x06, 0xb1, 0x20, 0x00, 0x10, 0x00,
+        0x00,
+    ]);
+
+    #[test]
+    fn test_from_cloud_trace_header() {
+        let test_cases = vec![
+            (
+                "105445aa7843bc8bf206b12000100000/1;o=1",
+                Some((1, SAMPLED_FLAG)),
+            ),
+            ("105445aa7843bc8bf206b12000100000/1;o=0", Some((1, 0))),
+            ("105445aa7843bc8bf206b12000100000/1", Some((1, 0))),
+            (
+                "105445AA7843BC8BF206B12000100000/18446744073709551615;o=3",
+                Some((u64::MAX, SAMPLED_FLAG)),
+            ),
+            ("105445aa7843bc8bf206b12000100000", None),
+            ("105445aa7843bc8bf206b12000100000/0;o=1", None),
+            ("00000000000000000000000000000000/1;o=1", None),
+            ("105445aa7843bc8bf206b1200010000/1;o=1", None),
+            ("105445aa7843bc8bf206b12000100000/-1;o=1", None),
+            (
+                "105445aa7843bc8bf206b12000100000/18446744073709551616",
+                None,
+            ),
+            ("105445aa7843bc8bf206b12000100000/1;x=1", None),
+            ("105445aa7843bc8bf206b12000100000/1;o=true", None),
+        ];
+
+        for (value, want) in test_cases {
+            let want = want.map(|(span_id, trace_options)| SpanContext {
+                trace_id: TID,
+                span_id: SpanID(u64::to_be_bytes(span_id)),
+                trace_options: TraceOptions(trace_options),
+                trace_state: None,
+            });
+            assert_eq!(from_cloud_trace_header(value), want, "{}", value);
+        }
+
+        let too_long = format!("105445aa7843bc8bf206b12000100000/{:0>200}", 1);
+        assert_eq!(from_cloud_trace_header(&too_long), None);
+    }
+
+    #[test]
+    fn cloud_trace_format_roundtrip() {
+        let sc = SpanContext {
+            trace_id: TID,
+            span_id: SpanID([0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]),
+            trace_options: TraceOptions(SAMPLED_FLAG),
+            trace_state: None,
+        };
+        assert_eq!(
+            to_cloud_trace_header(&sc),
+            "105445aa7843bc8bf206b12000100000/67667974448284343;o=1"
+        );
+
+        let mut carrier = HashMap::new();
+        CloudTraceFormat.inject(&sc, &mut carrier);
+        assert_eq!(CloudTraceFormat.extract(&carrier), Some(sc));
+    }
+}
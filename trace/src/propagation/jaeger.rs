@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use crate::basetypes::{SpanID, TraceID};
+use crate::propagation::{decode_hex, Extractor, Injector, TextFormat};
+use crate::trace::{SpanContext, TraceOptions, DEBUG_FLAG, SAMPLED_FLAG};
+
+/// TRACE_ID_HEADER is the header carrying the uber-trace-id value.
+pub const TRACE_ID_HEADER: &str = "uber-trace-id";
+/// BAGGAGE_HEADER_PREFIX is the prefix of the headers carrying baggage items.
+pub const BAGGAGE_HEADER_PREFIX: &str = "uberctx-";
+
+const JAEGER_SAMPLED_FLAG: u8 = 1;
+const JAEGER_DEBUG_FLAG: u8 = 2;
+
+/// uber-trace-id format:
+///
+/// Header value: <trace-id>:<span-id>:<parent-span-id>:<flags>
+///
+/// trace-id: up to 32 hex characters, leading zeroes may be omitted.
+/// span-id: up to 16 hex characters, leading zeroes may be omitted.
+/// parent-span-id: deprecated, always "0" when written.
+/// flags: one byte in hex, bit 0 is the sampled flag and bit 1 the debug flag.
+///
+/// The value may be URL encoded, in which case ":" is sent as "%3A".
+///
+/// Valid value example:
+///
+/// 4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:1
+///
+/// to_uber_trace_id returns the uber-trace-id header value for a SpanContext.
+pub fn to_uber_trace_id(sc: &SpanContext) -> String {
+    let mut flags = 0;
+    if sc.is_sampled() {
+        flags |= JAEGER_SAMPLED_FLAG;
+    }
+    if sc.trace_options.is_debug() {
+        flags |= JAEGER_DEBUG_FLAG;
+    }
+
+    let trace_id = sc.trace_id.to_string();
+    // 64-bit trace IDs are written without their zero high bits.
+    let trace_id = if sc.trace_id.0[..8] == [0; 8] {
+        &trace_id[16..]
+    } else {
+        &trace_id[..]
+    };
+    format!("{}:{}:0:{:x}", trace_id, sc.span_id, flags)
+}
+
+/// from_uber_trace_id returns the SpanContext represented by an
+/// uber-trace-id header value.
+///
+/// If the value is malformed or contains an all zero trace or span ID,
+/// from_uber_trace_id returns with None.
+pub fn from_uber_trace_id(value: &str) -> Option<SpanContext> {
+    let value = value.trim().replace("%3A", ":").replace("%3a", ":");
+    let mut fields = value.split(':');
+
+    let trace_id = TraceID(parse_padded_hex(fields.next()?)?);
+    let span_id = SpanID(parse_padded_hex(fields.next()?)?);
+    let _parent_span_id: [u8; 8] = parse_padded_hex(fields.next()?)?;
+    let flags = u8::from_str_radix(fields.next()?, 16).ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+    if trace_id == TraceID::default() || span_id == SpanID::default() {
+        return None;
+    }
+
+    let mut trace_options = TraceOptions::default();
+    if flags & JAEGER_SAMPLED_FLAG != 0 {
+        trace_options.0 |= SAMPLED_FLAG;
+    }
+    if flags & JAEGER_DEBUG_FLAG != 0 {
+        trace_options.0 |= DEBUG_FLAG;
+    }
+
+    Some(SpanContext {
+        trace_id,
+        span_id,
+        trace_options,
+        trace_state: None,
+    })
+}
+
+/// inject_baggage writes each baggage item to an uberctx-<key> header, with
+/// the value URL encoded.
+pub fn inject_baggage<'a>(
+    baggage: impl IntoIterator<Item = (&'a String, &'a String)>,
+    carrier: &mut dyn Injector,
+) {
+    for (key, value) in baggage {
+        carrier.set(
+            &format!("{}{}", BAGGAGE_HEADER_PREFIX, key),
+            url_encode(value),
+        );
+    }
+}
+
+/// extract_baggage returns the baggage items in the uberctx-<key> headers of
+/// the carrier. Keys are lowercased and values URL decoded.
+///
+/// The headers are found with Extractor::keys, so carriers that don't list
+/// their keys have no baggage.
+pub fn extract_baggage(carrier: &dyn Extractor) -> HashMap<String, String> {
+    carrier
+        .keys()
+        .into_iter()
+        .filter_map(|header| {
+            let lower = header.to_ascii_lowercase();
+            let key = lower.strip_prefix(BAGGAGE_HEADER_PREFIX)?;
+            let value = url_decode(carrier.get(header)?)?;
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// JaegerFormat propagates span contexts in the uber-trace-id header.
+///
+/// Baggage is propagated separately with inject_baggage and extract_baggage.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JaegerFormat;
+
+impl TextFormat for JaegerFormat {
+    fn fields(&self) -> &[&'static str] {
+        &[TRACE_ID_HEADER]
+    }
+
+    fn inject(&self, sc: &SpanContext, carrier: &mut dyn Injector) {
+        carrier.set(TRACE_ID_HEADER, to_uber_trace_id(sc));
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Option<SpanContext> {
+        from_uber_trace_id(carrier.get(TRACE_ID_HEADER)?)
+    }
+}
+
+/// parse_padded_hex decodes up to N bytes of hex, padding on the left with
+/// zeroes.
+fn parse_padded_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    if s.is_empty() || s.len() > N * 2 {
+        return None;
+    }
+    let padded = format!("{:0>width$}", s.to_ascii_lowercase(), width = N * 2);
+    let mut out = [0; N];
+    decode_hex(&padded, &mut out)?;
+    Some(out)
+}
+
+fn url_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(char::from(b))
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+fn url_decode(s: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let high = char::from(bytes.next()?).to_digit(16)?;
+                let low = char::from(bytes.next()?).to_digit(16)?;
+                decoded.push((high << 4 | low) as u8);
+            }
+            b'+' => decoded.push(b' '),
+            _ => decoded.push(b),
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TID: TraceID = TraceID([
+        0x4b, 0xf9, 0x2f, 0x35, 0x77, 0xb3, 0x4d, 0xa6, 0xa3, 0xce, 0x92, 0x9d, 0x0e, 0x0e, 0x47,
+        0x36,
+    ]);
+    const SHORT_TID: TraceID = TraceID([
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x92, 0x9d, 0x0e, 0x0e, 0x47, 0x36,
+    ]);
+    const SID: SpanID = SpanID([0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]);
+
+    #[test]
+    fn test_from_uber_trace_id() {
+        let test_cases = vec![
+            (
+                "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:1",
+                Some((TID, SAMPLED_FLAG)),
+            ),
+            (
+                "4bf92f3577b34da6a3ce929d0e0e4736:f067aa0ba902b7:53ce929d0e0e4736:0",
+                Some((TID, 0)),
+            ),
+            (
+                "929d0e0e4736:f067aa0ba902b7:0:3",
+                Some((SHORT_TID, SAMPLED_FLAG | DEBUG_FLAG)),
+            ),
+            (
+                "4BF92F3577B34DA6A3CE929D0E0E4736%3A00F067AA0BA902B7%3A0%3A1",
+                Some((TID, SAMPLED_FLAG)),
+            ),
+            (
+                "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:2",
+                Some((TID, DEBUG_FLAG)),
+            ),
+            ("4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0", None),
+            (
+                "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:1:1",
+                None,
+            ),
+            ("0:00f067aa0ba902b7:0:1", None),
+            ("4bf92f3577b34da6a3ce929d0e0e4736:0:0:1", None),
+            ("4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7::1", None),
+            (
+                "4bf92f3577b34da6a3ce929d0e0e47360:00f067aa0ba902b7:0:1",
+                None,
+            ),
+            (
+                "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:g",
+                None,
+            ),
+            (
+                "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:100",
+                None,
+            ),
+        ];
+
+        for (value, want) in test_cases {
+            let want = want.map(|(trace_id, trace_options)| SpanContext {
+                trace_id,
+                span_id: SID,
+                trace_options: TraceOptions(trace_options),
+                trace_state: None,
+            });
+            assert_eq!(from_uber_trace_id(value), want, "{}", value);
+        }
+    }
+
+    #[test]
+    fn uber_trace_id_roundtrip() {
+        for (trace_id, trace_options, want) in &[
+            (
+                TID,
+                SAMPLED_FLAG,
+                "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:1",
+            ),
+            (
+                TID,
+                SAMPLED_FLAG | DEBUG_FLAG,
+                "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:3",
+            ),
+            (SHORT_TID, 0, "0000929d0e0e4736:00f067aa0ba902b7:0:0"),
+        ] {
+            let sc = SpanContext {
+                trace_id: *trace_id,
+                span_id: SID,
+                trace_options: TraceOptions(*trace_options),
+                trace_state: None,
+            };
+            let value = to_uber_trace_id(&sc);
+            assert_eq!(&value, want);
+            assert_eq!(from_uber_trace_id(&value), Some(sc));
+        }
+    }
+
+    #[test]
+    fn baggage_roundtrip() {
+        let mut baggage = HashMap::new();
+        baggage.insert("user".to_string(), "alice smith".to_string());
+        baggage.insert("region".to_string(), "eu-west/1".to_string());
+
+        let mut carrier = HashMap::new();
+        inject_baggage(&baggage, &mut carrier);
+        carrier.insert("other".to_string(), "ignored".to_string());
+        assert_eq!(
+            carrier.get("uberctx-user").map(String::as_str),
+            Some("alice%20smith")
+        );
+        assert_eq!(
+            carrier.get("uberctx-region").map(String::as_str),
+            Some("eu-west%2F1")
+        );
+
+        assert_eq!(extract_baggage(&carrier), baggage);
+
+        let mut carrier = HashMap::new();
+        carrier.insert("Uberctx-Tenant".to_string(), "a+b".to_string());
+        carrier.insert("uberctx-bad".to_string(), "%zz".to_string());
+        carrier.insert("uberctx-sign".to_string(), "%+1".to_string());
+        carrier.insert("uberctx-short".to_string(), "%1".to_string());
+        let mut want = HashMap::new();
+        want.insert("tenant".to_string(), "a b".to_string());
+        assert_eq!(extract_baggage(&carrier), want);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::basetypes::{SpanID, TraceID};
+use crate::propagation::{decode_hex, Extractor, Injector, TextFormat};
+use crate::trace::{SpanContext, TraceOptions};
+use crate::tracestate::Tracestate;
+
+/// TRACEPARENT_HEADER is the name of the header carrying the traceparent value.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// TRACESTATE_HEADER is the name of the header carrying the tracestate value,
+/// see Tracestate for its format.
+pub const TRACESTATE_HEADER: &str = "tracestate";
+
+const SUPPORTED_VERSION: u8 = 0;
+const MAX_VERSION: u8 = 254;
+const TRACEPARENT_LEN: usize = 55;
+
+/// traceparent format:
+///
+/// Header value: <version>-<trace-id>-<parent-id>-<trace-flags>
+///
+/// version: 2 lowercase hex characters, "ff" is invalid.
+///
+/// For version = "00":
+///
+/// trace-id: 32 lowercase hex characters, all zeroes is invalid.
+/// parent-id: 16 lowercase hex characters, all zeroes is invalid.
+/// trace-flags: 2 lowercase hex characters, bit 0 is the sampled flag.
+///
+/// Later versions may append fields separated by "-", a parser that only
+/// understands version "00" reads the first four fields and ignores the rest.
+///
+/// Valid value example:
+///
+/// 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01
+///
+/// version = 0;
+/// trace_id = 4bf92f3577b34da6a3ce929d0e0e4736
+/// span_id = 00f067aa0ba902b7
+/// trace_options = 1
+///
+/// to_traceparent returns the traceparent header value for a SpanContext.
+pub fn to_traceparent(sc: &SpanContext) -> String {
+    format!(
+        "{:02x}-{}-{}-{:02x}",
+        SUPPORTED_VERSION, sc.trace_id, sc.span_id, sc.trace_options.0 as u8
+    )
+}
+
+/// from_traceparent returns the SpanContext represented by a traceparent
+/// header value.
+///
+/// If the value is malformed, has an invalid version or contains an all
+/// zero trace or span ID, from_traceparent returns with None.
+pub fn from_traceparent(value: &str) -> Option<SpanContext> {
+    let value = value.trim();
+    if value.len() < TRACEPARENT_LEN {
+        return None;
+    }
+
+    let mut version = [0; 1];
+    decode_hex(value.get(0..2)?, &mut version)?;
+    let version = version[0];
+    if version > MAX_VERSION {
+        return None;
+    }
+    if version == SUPPORTED_VERSION && value.len() != TRACEPARENT_LEN {
+        return None;
+    }
+    // future versions may only extend the format with more fields.
+    if value.len() > TRACEPARENT_LEN && value.as_bytes()[TRACEPARENT_LEN] != b'-' {
+        return None;
+    }
+
+    let mut fields = value[..TRACEPARENT_LEN].split('-').skip(1);
+
+    let mut trace_id = TraceID::default();
+    decode_hex(fields.next()?, &mut trace_id.0)?;
+    if trace_id == TraceID::default() {
+        return None;
+    }
+
+    let mut span_id = SpanID::default();
+    decode_hex(fields.next()?, &mut span_id.0)?;
+    if span_id == SpanID::default() {
+        return None;
+    }
+
+    let mut flags = [0; 1];
+    decode_hex(fields.next()?, &mut flags)?;
+
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(SpanContext {
+        trace_id,
+        span_id,
+        trace_options: TraceOptions(u32::from(flags[0])),
+        trace_state: None,
+    })
+}
+
+/// TraceContextFormat propagates span contexts in the W3C traceparent and
+/// tracestate headers.
+///
+/// A tracestate header that fails to parse is dropped without discarding
+/// the traceparent.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TraceContextFormat;
+
+impl TextFormat for TraceContextFormat {
+    fn fields(&self) -> &[&'static str] {
+        &[TRACEPARENT_HEADER, TRACESTATE_HEADER]
+    }
+
+    fn inject(&self, sc: &SpanContext, carrier: &mut dyn Injector) {
+        carrier.set(TRACEPARENT_HEADER, to_traceparent(sc));
+        if let Some(trace_state) = &sc.trace_state {
+            if trace_state.entries().len() > 0 {
+                carrier.set(TRACESTATE_HEADER, trace_state.to_string());
+            }
+        }
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Option<SpanContext> {
+        let mut sc = from_traceparent(carrier.get(TRACEPARENT_HEADER)?)?;
+        sc.trace_state = carrier
+            .get(TRACESTATE_HEADER)
+            .and_then(|value| value.parse().ok())
+            .filter(|trace_state: &Tracestate| trace_state.entries().len() > 0);
+        Some(sc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TID: TraceID = TraceID([
+        0x4b, 0xf9, 0x2f, 0x35, 0x77, 0xb3, 0x4d, 0xa6, 0xa3, 0xce, 0x92, 0x9d, 0x0e, 0x0e, 0x47,
+        0x36,
+    ]);
+    const SID: SpanID = SpanID([0x00, 0xf0, 0x67, 0xaa, 0x0b, 0xa9, 0x02, 0xb7]);
+
+    #[test]
+    fn traceparent_roundtrip() {
+        let sc = SpanContext {
+            trace_id: TID,
+            span_id: SID,
+            trace_options: TraceOptions(1),
+            trace_state: None,
+        };
+
+        let value = to_traceparent(&sc);
+        assert_eq!(
+            value,
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+        );
+        assert_eq!(from_traceparent(&value), Some(sc));
+    }
+
+    #[test]
+    fn trace_context_format_roundtrip() {
+        use std::collections::HashMap;
+
+        let sc = SpanContext {
+            trace_id: TID,
+            span_id: SID,
+            trace_options: TraceOptions(1),
+            trace_state: Some("rojo=00f067aa0ba902b7,congo=t61rcWkgMzE".parse().unwrap()),
+        };
+
+        let mut carrier = HashMap::new();
+        TraceContextFormat.inject(&sc, &mut carrier);
+        assert_eq!(
+            carrier.get(TRACESTATE_HEADER).map(String::as_str),
+            Some("rojo=00f067aa0ba902b7,congo=t61rcWkgMzE")
+        );
+        assert_eq!(TraceContextFormat.extract(&carrier), Some(sc));
+
+        carrier.insert(TRACESTATE_HEADER.to_string(), "Invalid".to_string());
+        let sc = TraceContextFormat.extract(&carrier).unwrap();
+        assert_eq!(sc.trace_id, TID);
+        assert_eq!(sc.trace_state, None);
+    }
+
+    #[test]
+    fn test_from_traceparent() {
+        struct TestCase {
+            value: &'static str,
+            want: Option<TraceOptions>,
+        }
+
+        let test_cases = [
+            TestCase {
+                value: "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                want: Some(TraceOptions(1)),
+            },
+            TestCase {
+                value: "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+                want: Some(TraceOptions(0)),
+            },
+            TestCase {
+                value: " 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01 ",
+                want: Some(TraceOptions(1)),
+            },
+            // future versions are parsed leniently.
+            TestCase {
+                value: "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                want: Some(TraceOptions(1)),
+            },
+            TestCase {
+                value:
+                    "cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-09-what-the-future-holds",
+                want: Some(TraceOptions(9)),
+            },
+            TestCase {
+                value: "cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01.what",
+                want: None,
+            },
+            TestCase {
+                value: "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                want: None,
+            },
+            TestCase {
+                value: "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+                want: None,
+            },
+            TestCase {
+                value: "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+                want: None,
+            },
+            TestCase {
+                value: "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+                want: None,
+            },
+            TestCase {
+                value: "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+                want: None,
+            },
+            TestCase {
+                value: "00-4bf92f3577b34da6a3ce929d0e0e473-600f067aa0ba902b7-01",
+                want: None,
+            },
+            TestCase {
+                value: "00_4bf92f3577b34da6a3ce929d0e0e4736_00f067aa0ba902b7_01",
+                want: None,
+            },
+            TestCase {
+                value: "0g-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                want: None,
+            },
+            TestCase {
+                value: "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+                want: None,
+            },
+            TestCase {
+                value: "",
+                want: None,
+            },
+        ];
+
+        for test_case in &test_cases {
+            let got = from_traceparent(test_case.value);
+            match test_case.want {
+                None => assert!(got.is_none(), "decoded {:?}", test_case.value),
+                Some(trace_options) => {
+                    let sc =
+                        got.unwrap_or_else(|| panic!("failed to decode {:?}", test_case.value));
+                    assert_eq!(sc.trace_id, TID);
+                    assert_eq!(sc.span_id, SID);
+                    assert_eq!(sc.trace_options, trace_options);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::basetypes::{SpanID, TraceID};
+use crate::propagation::{decode_hex, Extractor, Injector, TextFormat};
+use crate::trace::{SpanContext, TraceOptions, SAMPLED_FLAG};
+
+/// TRACE_ID_HEADER is the header carrying the X-Ray trace header.
+pub const TRACE_ID_HEADER: &str = "X-Amzn-Trace-Id";
+
+const ROOT_KEY: &str = "Root";
+const PARENT_KEY: &str = "Parent";
+const SAMPLED_KEY: &str = "Sampled";
+const TRACE_ID_VERSION: &str = "1";
+
+/// X-Ray trace header format:
+///
+/// Header value: Root=<trace-id>;Parent=<span-id>;Sampled=<sampling-state>
+///
+/// trace-id: "1-" followed by the epoch seconds of the trace start as 8 hex
+/// characters, "-" and 24 random hex characters. The epoch and random parts
+/// are mapped to the high 4 and low 12 bytes of the TraceID.
+/// span-id: 16 hex characters.
+/// sampling-state: "1" to accept, "0" to deny, "?" to defer sampling.
+///
+/// Fields may appear in any order. Unknown fields, such as Self or Lineage,
+/// and empty fields, such as the one after a trailing ';', are ignored.
+///
+/// Valid value example:
+///
+/// Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1
+///
+/// to_xray_header returns the X-Amzn-Trace-Id header value for a SpanContext.
+pub fn to_xray_header(sc: &SpanContext) -> String {
+    let trace_id = sc.trace_id.to_string();
+    format!(
+        "{}={}-{}-{};{}={};{}={}",
+        ROOT_KEY,
+        TRACE_ID_VERSION,
+        &trace_id[..8],
+        &trace_id[8..],
+        PARENT_KEY,
+        sc.span_id,
+        SAMPLED_KEY,
+        if sc.is_sampled() { "1" } else { "0" },
+    )
+}
+
+/// from_xray_header returns the SpanContext represented by an
+/// X-Amzn-Trace-Id header value.
+///
+/// If the root trace ID is missing or invalid, or the parent span ID is
+/// invalid, from_xray_header returns with None. A missing or deferred
+/// sampling decision is treated as a denial.
+///
+/// Load balancers start traces with a header that has no parent, e.g.
+/// Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1, for which the span ID
+/// is left as all zeroes.
+pub fn from_xray_header(value: &str) -> Option<SpanContext> {
+    let mut trace_id = None;
+    let mut span_id = None;
+    let mut trace_options = TraceOptions::default();
+
+    for field in value.split(';') {
+        let mut kv = field.trim().splitn(2, '=');
+        let (key, value) = match (kv.next(), kv.next()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => continue,
+        };
+        match key {
+            ROOT_KEY => trace_id = Some(parse_trace_id(value)?),
+            PARENT_KEY => span_id = Some(parse_span_id(value)?),
+            SAMPLED_KEY => {
+                trace_options = match value {
+                    "1" => TraceOptions(SAMPLED_FLAG),
+                    "0" | "?" => TraceOptions(0),
+                    _ => return None,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(SpanContext {
+        trace_id: trace_id?,
+        span_id: span_id.unwrap_or_default(),
+        trace_options,
+        trace_state: None,
+    })
+}
+
+/// XRayFormat propagates span contexts in the X-Amzn-Trace-Id header.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct XRayFormat;
+
+impl TextFormat for XRayFormat {
+    fn fields(&self) -> &[&'static str] {
+        &[TRACE_ID_HEADER]
+    }
+
+    fn inject(&self, sc: &SpanContext, carrier: &mut dyn Injector) {
+        carrier.set(TRACE_ID_HEADER, to_xray_header(sc));
+    }
+
+    fn extract(&self, carrier: &dyn Extractor) -> Option<SpanContext> {
+        from_xray_header(carrier.get(TRACE_ID_HEADER)?)
+    }
+}
+
+fn parse_trace_id(s: &str) -> Option<TraceID> {
+    let mut parts = s.split('-');
+    if parts.next()? != TRACE_ID_VERSION {
+        return None;
+    }
+    let epoch = parts.next()?.to_ascii_lowercase();
+    let random = parts.next()?.to_ascii_lowercase();
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let mut trace_id = TraceID::default();
+    decode_hex(&epoch, &mut trace_id.0[..4])?;
+    decode_hex(&random, &mut trace_id.0[4..])?;
+    if trace_id == TraceID::default() {
+        return None;
+    }
+    Some(trace_id)
+}
+
+fn parse_span_id(s: &str) -> Option<SpanID> {
+    let mut span_id = SpanID::default();
+    decode_hex(&s.to_ascii_lowercase(), &mut span_id.0)?;
+    if span_id == SpanID::default() {
+        return None;
+    }
+    Some(span_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    const TID: TraceID = TraceID([
+        0x57, 0x59, 0xe9, 0x88, 0xbd, 0x86, 0x2e, 0x3f, 0xe1, 0xbe, 0x46, 0xa9, 0x94, 0x27, 0x27,
+        0x93,
+    ]);
+    const SID: SpanID = SpanID([0x53, 0x99, 0x5c, 0x3f, 0x42, 0xcd, 0x8a, 0xd8]);
+
+    #[test]
+    fn test_from_xray_header() {
+        let test_cases = vec![
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1",
+                Some(SAMPLED_FLAG),
+            ),
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=0",
+                Some(0),
+            ),
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=?",
+                Some(0),
+            ),
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8",
+                Some(0),
+            ),
+            (
+                "Self=1-67891234-12456789abcdef012345678;Sampled=1; Parent=53995C3F42CD8AD8;Root=1-5759E988-BD862E3FE1BE46A994272793",
+                Some(SAMPLED_FLAG),
+            ),
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1;",
+                Some(SAMPLED_FLAG),
+            ),
+            (
+                ";Root=1-5759e988-bd862e3fe1be46a994272793;;Parent=53995c3f42cd8ad8;Lineage",
+                Some(0),
+            ),
+            ("Parent=53995c3f42cd8ad8;Sampled=1", None),
+            (
+                "Root=2-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8",
+                None,
+            ),
+            (
+                "Root=1-5759e988bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8",
+                None,
+            ),
+            (
+                "Root=1-00000000-000000000000000000000000;Parent=53995c3f42cd8ad8",
+                None,
+            ),
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=0000000000000000",
+                None,
+            ),
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=yes",
+                None,
+            ),
+            ("Root", None),
+        ];
+
+        for (value, want) in test_cases {
+            let want = want.map(|trace_options| SpanContext {
+                trace_id: TID,
+                span_id: SID,
+                trace_options: TraceOptions(trace_options),
+                trace_state: None,
+            });
+            assert_eq!(from_xray_header(value), want, "{}", value);
+        }
+    }
+
+    #[test]
+    fn root_only_header() {
+        for (value, trace_options) in &[
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1",
+                SAMPLED_FLAG,
+            ),
+            (
+                "Root=1-5759e988-bd862e3fe1be46a994272793;Sampled=1;",
+                SAMPLED_FLAG,
+            ),
+            ("Root=1-5759e988-bd862e3fe1be46a994272793", 0),
+        ] {
+            assert_eq!(
+                from_xray_header(value),
+                Some(SpanContext {
+                    trace_id: TID,
+                    span_id: SpanID::default(),
+                    trace_options: TraceOptions(*trace_options),
+                    trace_state: None,
+                }),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn xray_format_roundtrip() {
+        let sc = SpanContext {
+            trace_id: TID,
+            span_id: SID,
+            trace_options: TraceOptions(SAMPLED_FLAG),
+            trace_state: None,
+        };
+        assert_eq!(
+            to_xray_header(&sc),
+            "Root=1-5759e988-bd862e3fe1be46a994272793;Parent=53995c3f42cd8ad8;Sampled=1"
+        );
+
+        let mut carrier = HashMap::new();
+        XRayFormat.inject(&sc, &mut carrier);
+        assert_eq!(XRayFormat.extract(&carrier), Some(sc));
+    }
+}
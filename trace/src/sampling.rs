@@ -41,7 +41,7 @@ pub fn probability_sampler(mut fraction: f64) -> Sampler {
         return always_sample();
     }
 
-    let trace_id_upper_bound = (fraction * ((1 as u64) << 63) as f64).floor() as u64;
+    let trace_id_upper_bound = (fraction * (1_u64 << 63) as f64).floor() as u64;
     Arc::new(move |sampling_params: SamplingParameters<'_>| {
         if let Some(parent_context) = sampling_params.parent_context {
             if parent_context.is_sampled() {
@@ -41,11 +41,13 @@ impl Bucket {
     }
 
     pub fn add(&mut self, s: SpanData) {
-        if let Some(end_time) = s.end_time {
+        if s.end_time.is_some() {
             if self.buffer.is_empty() {
                 return;
             }
-            self.next_time = end_time + SAMPLE_PERIOD;
+            // spans are added as they end, and the sample period is measured
+            // on the monotonic clock so wall clock changes can't skew it.
+            self.next_time = Instant::now() + SAMPLE_PERIOD;
             self.buffer[self.next_index] = s;
             self.next_index += 1;
             if self.next_index == self.buffer.len() {
@@ -102,10 +104,7 @@ pub fn latency_bucket_bounds(idx: usize) -> (Duration, Duration) {
     if idx == 0 {
         (Duration::new(0, 0), DEFAULT_LATENCIES[idx])
     } else if idx == DEFAULT_LATENCIES.len() {
-        (
-            DEFAULT_LATENCIES[idx - 1],
-            Duration::from_secs(u64::max_value()),
-        )
+        (DEFAULT_LATENCIES[idx - 1], Duration::from_secs(u64::MAX))
     } else {
         (DEFAULT_LATENCIES[idx - 1], DEFAULT_LATENCIES[idx])
     }
@@ -33,8 +33,8 @@ struct SpanStoreContents {
     max_spans_per_error_bucket: usize,
 }
 
-impl<'a> SpanStore {
-    pub fn new(name: &str, latency_bucket_size: usize, error_bucket_size: usize) -> Self {
+impl SpanStore {
+    pub fn new(_name: &str, latency_bucket_size: usize, error_bucket_size: usize) -> Self {
         let latency = (0..=(DEFAULT_LATENCIES.len()))
             .map(|_| Bucket::new(latency_bucket_size))
             .collect();
@@ -58,14 +58,14 @@ impl<'a> SpanStore {
         contents.max_spans_per_error_bucket = error_bucket_size;
     }
 
-    fn add(&mut self, span: Span) {
-        let contents = self.0.lock().unwrap();
+    fn add(&mut self, _span: Span) {
+        let _contents = self.0.lock().unwrap();
         // contents.active.insert(Span)
     }
 
     pub fn finished(&self, sd: SpanData) {
-        let end_time = sd.end_time.unwrap_or_else(time::Instant::now);
-        let latency = end_time.duration_since(sd.start_time);
+        let end_time = sd.end_time.unwrap_or_else(time::SystemTime::now);
+        let latency = end_time.duration_since(sd.start_time).unwrap_or_default();
         let code = sd
             .status
             .clone()
@@ -1,28 +1,225 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
 /// Status codes for use with Span.SetStatus. These correspond to the status
 /// codes used by gRPC defined here: https://github.com/googleapis/googleapis/blob/master/google/rpc/code.proto
-#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
 pub enum StatusCode {
+    /// Not an error, returned on success.
+    #[default]
     OK = 0,
+    /// The operation was cancelled, typically by the caller.
     Cancelled = 1,
+    /// Unknown error, for errors that carry no further information.
     Unknown = 2,
+    /// The client specified an invalid argument.
     InvalidArgument = 3,
+    /// The deadline expired before the operation could complete.
     DeadlineExceeded = 4,
+    /// Some requested entity was not found.
     NotFound = 5,
+    /// The entity that a client attempted to create already exists.
     AlreadyExists = 6,
+    /// The caller does not have permission to execute the operation.
     PermissionDenied = 7,
+    /// Some resource has been exhausted.
     ResourceExhausted = 8,
+    /// The system is not in a state required for the operation.
     FailedPrecondition = 9,
+    /// The operation was aborted, typically due to a concurrency issue.
     Aborted = 10,
+    /// The operation was attempted past the valid range.
     OutOfRange = 11,
+    /// The operation is not implemented or supported.
     Unimplemented = 12,
+    /// Internal error.
     Internal = 13,
+    /// The service is currently unavailable.
     Unavailable = 14,
+    /// Unrecoverable data loss or corruption.
     DataLoss = 15,
+    /// The request does not have valid authentication credentials.
     Unauthenticated = 16,
 }
 
-impl Default for StatusCode {
-    fn default() -> StatusCode {
-        StatusCode::OK
+// Canonical names of the status codes, indexed by code.
+const NAMES: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+const CODES: [StatusCode; 17] = [
+    StatusCode::OK,
+    StatusCode::Cancelled,
+    StatusCode::Unknown,
+    StatusCode::InvalidArgument,
+    StatusCode::DeadlineExceeded,
+    StatusCode::NotFound,
+    StatusCode::AlreadyExists,
+    StatusCode::PermissionDenied,
+    StatusCode::ResourceExhausted,
+    StatusCode::FailedPrecondition,
+    StatusCode::Aborted,
+    StatusCode::OutOfRange,
+    StatusCode::Unimplemented,
+    StatusCode::Internal,
+    StatusCode::Unavailable,
+    StatusCode::DataLoss,
+    StatusCode::Unauthenticated,
+];
+
+/// HTTP response statuses convert to status codes following the OpenCensus
+/// HTTP specification.
+///
+/// 1xx and 4xx or 5xx statuses without a more specific mapping are Unknown,
+/// and all other statuses are OK.
+impl From<u16> for StatusCode {
+    fn from(status: u16) -> StatusCode {
+        match status {
+            400 => StatusCode::InvalidArgument,
+            401 => StatusCode::Unauthenticated,
+            403 => StatusCode::PermissionDenied,
+            404 => StatusCode::NotFound,
+            429 => StatusCode::ResourceExhausted,
+            499 => StatusCode::Cancelled,
+            501 => StatusCode::Unimplemented,
+            503 => StatusCode::Unavailable,
+            504 => StatusCode::DeadlineExceeded,
+            200..=399 => StatusCode::OK,
+            _ => StatusCode::Unknown,
+        }
+    }
+}
+
+/// InvalidStatusCode is returned when converting an integer that isn't a
+/// canonical status code.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct InvalidStatusCode(pub i32);
+
+impl fmt::Display for InvalidStatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid status code {}", self.0)
+    }
+}
+
+impl Error for InvalidStatusCode {}
+
+impl TryFrom<i32> for StatusCode {
+    type Error = InvalidStatusCode;
+
+    fn try_from(code: i32) -> Result<Self, Self::Error> {
+        usize::try_from(code)
+            .ok()
+            .and_then(|i| CODES.get(i))
+            .cloned()
+            .ok_or(InvalidStatusCode(code))
+    }
+}
+
+impl From<StatusCode> for i32 {
+    fn from(code: StatusCode) -> Self {
+        code as i32
+    }
+}
+
+/// Status codes are displayed with their canonical names, such as
+/// INVALID_ARGUMENT.
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(NAMES[self.clone() as usize])
+    }
+}
+
+/// ParseStatusCodeError is returned when parsing a string that isn't the
+/// canonical name of a status code.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParseStatusCodeError(pub String);
+
+impl fmt::Display for ParseStatusCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown status code name {:?}", self.0)
+    }
+}
+
+impl Error for ParseStatusCodeError {}
+
+impl FromStr for StatusCode {
+    type Err = ParseStatusCodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NAMES
+            .iter()
+            .position(|name| *name == s)
+            .map(|i| CODES[i].clone())
+            .ok_or_else(|| ParseStatusCodeError(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_http_status() {
+        let test_cases = vec![
+            (100, StatusCode::Unknown),
+            (200, StatusCode::OK),
+            (204, StatusCode::OK),
+            (302, StatusCode::OK),
+            (400, StatusCode::InvalidArgument),
+            (401, StatusCode::Unauthenticated),
+            (403, StatusCode::PermissionDenied),
+            (404, StatusCode::NotFound),
+            (409, StatusCode::Unknown),
+            (422, StatusCode::Unknown),
+            (429, StatusCode::ResourceExhausted),
+            (499, StatusCode::Cancelled),
+            (500, StatusCode::Unknown),
+            (501, StatusCode::Unimplemented),
+            (503, StatusCode::Unavailable),
+            (504, StatusCode::DeadlineExceeded),
+        ];
+        for (status, want) in test_cases {
+            assert_eq!(StatusCode::from(status), want, "{}", status);
+        }
+    }
+
+    #[test]
+    fn integer_roundtrip() {
+        for i in 0..=16 {
+            let code = StatusCode::try_from(i).unwrap();
+            assert_eq!(i32::from(code), i);
+        }
+        assert_eq!(StatusCode::try_from(-1), Err(InvalidStatusCode(-1)));
+        assert_eq!(StatusCode::try_from(17), Err(InvalidStatusCode(17)));
+    }
+
+    #[test]
+    fn name_roundtrip() {
+        for code in CODES.iter() {
+            assert_eq!(code.to_string().parse::<StatusCode>().as_ref(), Ok(code));
+        }
+        assert_eq!(StatusCode::InvalidArgument.to_string(), "INVALID_ARGUMENT");
+        assert_eq!(
+            "invalid_argument".parse::<StatusCode>(),
+            Err(ParseStatusCodeError("invalid_argument".to_string()))
+        );
     }
 }
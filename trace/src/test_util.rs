@@ -0,0 +1,47 @@
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::basetypes::{Attributes, SpanID, TraceID};
+use crate::export::{Exporter, SpanData};
+use crate::trace::{SpanContext, SpanKind, TraceOptions};
+
+/// TestExporter collects the spans exported to it.
+#[derive(Default)]
+pub struct TestExporter {
+    /// exported_spans holds the exported spans, oldest first.
+    pub exported_spans: Mutex<Vec<SpanData>>,
+}
+
+impl Exporter for TestExporter {
+    fn export_span(&self, s: &SpanData) {
+        self.exported_spans.lock().unwrap().push(s.clone())
+    }
+}
+
+/// span_data returns an ended, sampled span with a name and no events, that
+/// started 1000µs after the Unix epoch and lasted 500µs.
+pub fn span_data(name: &str) -> SpanData {
+    SpanData {
+        span_context: SpanContext {
+            trace_id: TraceID([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]),
+            span_id: SpanID([1, 2, 3, 4, 5, 6, 7, 8]),
+            trace_options: TraceOptions(1),
+            trace_state: None,
+        },
+        parent_span_id: None,
+        span_kind: SpanKind::Unspecified,
+        name: name.to_string(),
+        start_time: UNIX_EPOCH + Duration::from_micros(1_000),
+        end_time: Some(UNIX_EPOCH + Duration::from_micros(1_500)),
+        attributes: Attributes::new(),
+        annotations: Vec::new(),
+        message_events: Vec::new(),
+        status: None,
+        links: Vec::new(),
+        has_remote_parent: false,
+        dropped_attribute_count: 0,
+        dropped_annotation_count: 0,
+        dropped_message_event_count: 0,
+        dropped_link_count: 0,
+    }
+}
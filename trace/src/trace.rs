@@ -1,16 +1,21 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use std::iter::IntoIterator;
+use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Once, RwLock};
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 
 use io_context::Context;
 
-use crate::basetypes::{AttributeValue, Link, SpanID, Status, TraceID};
-use crate::config;
+use crate::basetypes::{
+    Annotation, AttributeValue, Link, MessageEvent, MessageEventType, SpanID, Status, TraceID,
+};
+use crate::config::{self, SpanLimits};
 use crate::export::{SpanData, EXPORTERS};
+use crate::propagation::{Extractor, Injector, TextFormat};
 use crate::sampling::{Sampler, SamplingParameters};
 use crate::spanstore::SpanStore;
+use crate::status_codes::StatusCode;
 use crate::tracestate::Tracestate;
 
 /// Span represents a span of a trace.  It has an associated SpanContext, and
@@ -28,7 +33,7 @@ pub struct Span {
     /// It will be some if we are exporting the span or recording events for it.
     /// Otherwise, data is none, and the Span is simply a carrier for the
     /// SpanContext, so that the trace ID is propagated.
-    data: Option<Arc<RwLock<SpanData>>>,
+    data: Option<Arc<RwLock<SpanState>>>,
     span_context: SpanContext,
     span_store: Option<Arc<SpanStore>>,
     end_once: Arc<Once>,
@@ -36,14 +41,11 @@ pub struct Span {
 
 /// start_span starts a span in a context with a name and options.
 pub fn start_span(ctx: &Arc<Context>, name: &str, o: &[StartOption]) -> (Context, Span) {
-    let mut opts = StartOptions::default();
+    let opts = StartOptions::from_options(o);
     let parent = from_context(ctx).map(|p| &p.span_context);
-    for op in o {
-        op(&mut opts);
-    }
     let span = start_span_internal(name, parent, false, &opts);
 
-    (new_context(&ctx, span.clone()), span)
+    (new_context(ctx, span.clone()), span)
 }
 
 /// start_span starts a span in a context with a name and options with a parent span.
@@ -53,25 +55,87 @@ pub fn start_span_with_remote_parent(
     parent: &SpanContext,
     o: &[StartOption],
 ) -> (Context, Span) {
-    let mut opts = StartOptions::default();
-    for op in o.iter() {
-        op(&mut opts);
-    }
-
+    let opts = StartOptions::from_options(o);
     let span = start_span_internal(name, Some(parent), true, &opts);
 
-    (new_context(&ctx, span.clone()), span)
+    (new_context(ctx, span.clone()), span)
+}
+
+/// start_child_span starts a span with a name and options as a local child of
+/// parent, without going through a Context.
+///
+/// As with start_span, the sampling decision of parent is kept unless a
+/// sampler is given in the options.
+pub fn start_child_span(name: &str, parent: &SpanContext, o: &[StartOption]) -> Span {
+    let opts = StartOptions::from_options(o);
+    start_span_internal(name, Some(parent), false, &opts)
+}
+
+/// start_producer_span starts a producer span for an outgoing message and
+/// injects its span context into the message properties using format.
+///
+/// It is a child of the span in ctx, if any.
+pub fn start_producer_span(
+    ctx: &Arc<Context>,
+    name: &str,
+    format: &dyn TextFormat,
+    carrier: &mut dyn Injector,
+    o: &[StartOption],
+) -> (Context, Span) {
+    let mut opts = StartOptions::from_options(o);
+    opts.span_kind = SpanKind::Producer;
+
+    let parent = from_context(ctx).map(|p| p.span_context());
+    let span = start_span_internal(name, parent, false, &opts);
+    format.inject(span.span_context(), carrier);
+
+    (new_context(ctx, span.clone()), span)
 }
 
-fn start_span_internal(
+/// start_consumer_span starts a consumer span for an incoming message.
+///
+/// If format finds a span context in the message properties, the span is
+/// started with it as a remote parent, otherwise it is a child of the span
+/// in ctx, if any.
+pub fn start_consumer_span(
+    ctx: &Arc<Context>,
+    name: &str,
+    format: &dyn TextFormat,
+    carrier: &dyn Extractor,
+    o: &[StartOption],
+) -> (Context, Span) {
+    let mut opts = StartOptions::from_options(o);
+    opts.span_kind = SpanKind::Consumer;
+
+    let span = match format.extract(carrier) {
+        Some(remote_parent) => start_span_internal(name, Some(&remote_parent), true, &opts),
+        None => {
+            let parent = from_context(ctx).map(|p| p.span_context());
+            start_span_internal(name, parent, false, &opts)
+        }
+    };
+
+    (new_context(ctx, span.clone()), span)
+}
+
+/// start_span_guarded starts a span like start_span, returning a SpanGuard
+/// that ends the span when it is dropped.
+pub fn start_span_guarded(
+    ctx: &Arc<Context>,
+    name: &str,
+    o: &[StartOption],
+) -> (Context, SpanGuard) {
+    let (ctx, span) = start_span(ctx, name, o);
+    (ctx, SpanGuard::new(span))
+}
+
+pub(crate) fn start_span_internal(
     name: &str,
     parent: Option<&SpanContext>,
     remote_parent: bool,
     o: &StartOptions,
 ) -> Span {
-    let mut span_context = parent
-        .map(SpanContext::clone)
-        .unwrap_or_else(SpanContext::default);
+    let mut span_context = parent.cloned().unwrap_or_default();
 
     let cfg = config::load_config();
 
@@ -108,12 +172,17 @@ fn start_span_internal(
         };
     }
 
+    let now = SystemTime::now();
     let data = SpanData {
         span_context: span_context.clone(),
-        parent_span_id: parent.map(|p| p.span_id),
+        // a remote parent may only carry a trace ID, e.g. an X-Ray header
+        // added by a load balancer.
+        parent_span_id: parent
+            .map(|p| p.span_id)
+            .filter(|id| *id != SpanID::default()),
         span_kind: o.span_kind,
         name: name.to_string(),
-        start_time: Instant::now(),
+        start_time: o.start_time.unwrap_or(now),
         end_time: None,
         attributes: HashMap::new(),
         annotations: Vec::new(),
@@ -121,10 +190,24 @@ fn start_span_internal(
         status: None,
         links: Vec::new(),
         has_remote_parent: remote_parent,
+        dropped_attribute_count: 0,
+        dropped_annotation_count: 0,
+        dropped_message_event_count: 0,
+        dropped_link_count: 0,
+    };
+    let state = SpanState {
+        data,
+        attribute_keys: VecDeque::new(),
+        annotations: VecDeque::new(),
+        message_events: VecDeque::new(),
+        links: VecDeque::new(),
+        limits: cfg.span_limits,
+        wall_anchor: now,
+        monotonic_anchor: Instant::now(),
     };
 
     Span {
-        data: Some(Arc::new(RwLock::new(data))),
+        data: Some(Arc::new(RwLock::new(state))),
         span_context,
         span_store: None,
         end_once: Arc::new(Once::new()),
@@ -134,6 +217,17 @@ fn start_span_internal(
 impl Span {
     /// end closes the span and exports it if it should be exported.
     pub fn end(self) {
+        self.end_at(None);
+    }
+
+    /// end_with_time closes the span at the given end time and exports it if
+    /// it should be exported. It is meant for spans recorded after the fact,
+    /// along with the with_start_time StartOption.
+    pub fn end_with_time(self, end_time: SystemTime) {
+        self.end_at(Some(end_time));
+    }
+
+    fn end_at(self, end_time: Option<SystemTime>) {
         if !self.is_recording_events() {
             return;
         }
@@ -143,7 +237,9 @@ impl Span {
             let must_export = self.span_context.is_sampled() && !exporters.is_empty();
             if self.span_store.is_some() || must_export {
                 if let Some(mut span_data) = self.make_span_data() {
-                    span_data.end_time = Some(Instant::now());
+                    if end_time.is_some() {
+                        span_data.end_time = end_time;
+                    }
                     // export first so we can borrow SpanData and then
                     // move it into the store.
                     if must_export {
@@ -160,18 +256,28 @@ impl Span {
         });
     }
 
+    /// start_child starts a span with a name and options as a child of this
+    /// span, see start_child_span.
+    pub fn start_child(&self, name: &str, o: &[StartOption]) -> Span {
+        start_child_span(name, &self.span_context, o)
+    }
+
     /// is_recording_events indicates whether a span is recording events.
     pub fn is_recording_events(&self) -> bool {
         self.data.is_some()
     }
 
+    /// make_span_data returns a copy of the recorded data, ended at the
+    /// current time.
     fn make_span_data(&self) -> Option<SpanData> {
-        if let Some(data) = &self.data {
-            let data = data.read().unwrap();
-            Some((*data).clone())
-        } else {
-            None
-        }
+        let data = self.data.as_ref()?;
+        let state = data.read().unwrap();
+        let mut span_data = state.data.clone();
+        span_data.annotations = state.annotations.iter().cloned().collect();
+        span_data.message_events = state.message_events.iter().cloned().collect();
+        span_data.links = state.links.iter().cloned().collect();
+        span_data.end_time = Some(state.now());
+        Some(span_data)
     }
 
     /// span_context gets a reference to the span context of the span.
@@ -182,32 +288,151 @@ impl Span {
     /// set_name sets the name of the span.
     pub fn set_name(&mut self, name: &str) {
         if let Some(data) = &self.data {
-            let mut data = data.write().unwrap();
-            (*data).name = name.to_string();
+            let mut state = data.write().unwrap();
+            state.data.name = name.to_string();
         }
     }
 
     /// set_name sets the status of the span.
     pub fn set_status(&mut self, status: &Status) {
         if let Some(data) = &self.data {
-            let mut data = data.write().unwrap();
-            (*data).status = Some(status.clone());
+            let mut state = data.write().unwrap();
+            state.data.status = Some(status.clone());
         }
     }
 
-    /// add_attributes adds an iterable of attributes to the span.
+    /// set_status_from_error sets the status of the span from an error and
+    /// its chain of sources, see Status::from_error.
+    pub fn set_status_from_error(&mut self, err: &(dyn std::error::Error + 'static)) {
+        self.set_status(&Status::from_error(err));
+    }
+
+    /// add_attributes adds an iterable of attributes to the span, replacing
+    /// the values of any keys that are already set.
+    ///
+    /// If the span holds more attributes than allowed by its SpanLimits, the
+    /// least recently set attributes are dropped.
     pub fn add_attributes(&mut self, attrs: impl IntoIterator<Item = (String, AttributeValue)>) {
         if let Some(data) = &self.data {
-            let mut data = data.write().unwrap();
-            (*data).attributes = attrs.into_iter().collect();
+            let mut state = data.write().unwrap();
+            for (key, value) in attrs {
+                state.set_attribute(key, value);
+            }
         }
     }
 
     /// add_link adds a link to a span.
+    ///
+    /// If the span holds more links than allowed by its SpanLimits, the
+    /// oldest link is dropped.
     pub fn add_link(&mut self, l: Link) {
         if let Some(data) = &self.data {
-            let mut data = data.write().unwrap();
-            (*data).links.push(l);
+            let mut state = data.write().unwrap();
+            let state = &mut *state;
+            push_bounded(
+                &mut state.links,
+                l,
+                state.limits.max_links_per_span,
+                &mut state.data.dropped_link_count,
+            );
+        }
+    }
+
+    /// annotate adds an annotation with a message to the span.
+    pub fn annotate(&mut self, message: &str) {
+        self.annotate_with_attributes(message, Vec::new());
+    }
+
+    /// annotate_with_attributes adds an annotation with a message and an
+    /// iterable of attributes to the span.
+    ///
+    /// If the span holds more annotations than allowed by its SpanLimits, the
+    /// oldest annotation is dropped.
+    pub fn annotate_with_attributes(
+        &mut self,
+        message: &str,
+        attrs: impl IntoIterator<Item = (String, AttributeValue)>,
+    ) {
+        if let Some(data) = &self.data {
+            let mut state = data.write().unwrap();
+            let state = &mut *state;
+            let annotation = Annotation {
+                time: state.now(),
+                message: message.to_string(),
+                attributes: attrs.into_iter().collect(),
+            };
+            push_bounded(
+                &mut state.annotations,
+                annotation,
+                state.limits.max_annotation_events_per_span,
+                &mut state.data.dropped_annotation_count,
+            );
+        }
+    }
+
+    /// add_message_send_event adds a message send event to the span.
+    ///
+    /// message_id is an identifier for the message, which is recommended to be
+    /// unique in this span and the same between the send event and the receive
+    /// event (this allows to identify a message between the sender and receiver).
+    /// For example, this could be a sequence id.
+    pub fn add_message_send_event(
+        &mut self,
+        message_id: i64,
+        uncompressed_byte_size: i64,
+        compressed_byte_size: i64,
+    ) {
+        self.add_message_event(
+            MessageEventType::Sent,
+            message_id,
+            uncompressed_byte_size,
+            compressed_byte_size,
+        );
+    }
+
+    /// add_message_receive_event adds a message receive event to the span.
+    ///
+    /// message_id is an identifier for the message, which is recommended to be
+    /// unique in this span and the same between the send event and the receive
+    /// event (this allows to identify a message between the sender and receiver).
+    /// For example, this could be a sequence id.
+    pub fn add_message_receive_event(
+        &mut self,
+        message_id: i64,
+        uncompressed_byte_size: i64,
+        compressed_byte_size: i64,
+    ) {
+        self.add_message_event(
+            MessageEventType::Recv,
+            message_id,
+            uncompressed_byte_size,
+            compressed_byte_size,
+        );
+    }
+
+    fn add_message_event(
+        &mut self,
+        event_type: MessageEventType,
+        message_id: i64,
+        uncompressed_byte_size: i64,
+        compressed_byte_size: i64,
+    ) {
+        if let Some(data) = &self.data {
+            let mut state = data.write().unwrap();
+            let state = &mut *state;
+            let event = MessageEvent {
+                time: state.now(),
+                event_type,
+                message_id,
+                uncompressed_byte_size,
+                compressed_byte_size,
+            };
+            push_bounded(
+                &mut state.message_events,
+                event,
+                state.limits.max_message_events_per_span,
+                &mut state.data.dropped_message_event_count,
+            );
         }
     }
 }
@@ -215,8 +440,8 @@ impl Span {
 impl fmt::Display for Span {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(data) = &self.data {
-            let data = data.read().unwrap();
-            write!(f, "span {} {}", self.span_context.span_id, data.name)?;
+            let state = data.read().unwrap();
+            write!(f, "span {} {}", self.span_context.span_id, state.data.name)?;
         } else {
             write!(f, "span {}", self.span_context.span_id)?;
         }
@@ -224,6 +449,113 @@ impl fmt::Display for Span {
     }
 }
 
+/// SpanState is the data recorded by a span, along with what is needed to
+/// enforce its limits.
+#[derive(Debug)]
+struct SpanState {
+    data: SpanData,
+    /// attribute_keys holds the attribute keys from least to most recently
+    /// set.
+    attribute_keys: VecDeque<String>,
+    /// annotations, message_events and links are kept here rather than in
+    /// data, so that the oldest can be dropped cheaply.
+    annotations: VecDeque<Annotation>,
+    message_events: VecDeque<MessageEvent>,
+    links: VecDeque<Link>,
+    limits: SpanLimits,
+    /// wall_anchor and monotonic_anchor are the wall clock and monotonic
+    /// times the span was started at, from which the wall clock times of
+    /// later events are derived.
+    wall_anchor: SystemTime,
+    monotonic_anchor: Instant,
+}
+
+impl SpanState {
+    /// now returns the current wall clock time, offset from the time the
+    /// span was started at by the monotonic time elapsed since.
+    fn now(&self) -> SystemTime {
+        self.wall_anchor + self.monotonic_anchor.elapsed()
+    }
+
+    fn set_attribute(&mut self, key: String, value: AttributeValue) {
+        if self.data.attributes.insert(key.clone(), value).is_some() {
+            if let Some(i) = self.attribute_keys.iter().position(|k| *k == key) {
+                self.attribute_keys.remove(i);
+            }
+        }
+        self.attribute_keys.push_back(key);
+
+        while self.attribute_keys.len() > self.limits.max_attributes_per_span {
+            if let Some(oldest) = self.attribute_keys.pop_front() {
+                self.data.attributes.remove(&oldest);
+                self.data.dropped_attribute_count += 1;
+            }
+        }
+    }
+}
+
+/// push_bounded appends item to v, dropping the oldest items and counting
+/// them in dropped while v holds more than max items.
+fn push_bounded<T>(v: &mut VecDeque<T>, item: T, max: usize, dropped: &mut usize) {
+    v.push_back(item);
+    while v.len() > max {
+        v.pop_front();
+        *dropped += 1;
+    }
+}
+
+/// SpanGuard ends the span it holds when it is dropped, so that early returns
+/// don't leave the span unfinished.
+///
+/// If the guard is dropped while the thread is panicking, the span status is
+/// set to Internal before it is ended. Spans handed off to another task should
+/// be taken out of the guard with into_span.
+#[derive(Debug)]
+pub struct SpanGuard {
+    span: Option<Span>,
+}
+
+impl SpanGuard {
+    /// new returns a SpanGuard that ends span when dropped.
+    pub fn new(span: Span) -> Self {
+        SpanGuard { span: Some(span) }
+    }
+
+    /// into_span returns the span without ending it. The caller is then
+    /// responsible for calling end.
+    pub fn into_span(mut self) -> Span {
+        self.span.take().unwrap()
+    }
+}
+
+impl Deref for SpanGuard {
+    type Target = Span;
+
+    fn deref(&self) -> &Span {
+        self.span.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for SpanGuard {
+    fn deref_mut(&mut self) -> &mut Span {
+        self.span.as_mut().unwrap()
+    }
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        if let Some(mut span) = self.span.take() {
+            if std::thread::panicking() {
+                span.set_status(&Status {
+                    code: StatusCode::Internal,
+                    message: "span dropped during a panic".to_string(),
+                });
+            }
+            span.end();
+        }
+    }
+}
+
 const SPAN_ID_KEY: &str = "OPENCENSUS_TRACE_SPAN_ID_KEY";
 
 pub fn from_context(ctx: &Context) -> Option<&Span> {
@@ -259,13 +591,22 @@ impl SpanContext {
     /// span will be exported.
     fn set_is_sampled(&mut self, sampled: bool) {
         if sampled {
-            self.trace_options.0 |= 1
+            self.trace_options.0 |= SAMPLED_FLAG
         } else {
-            self.trace_options.0 &= !1
+            self.trace_options.0 &= !SAMPLED_FLAG
         }
     }
 }
 
+/// SAMPLED_FLAG is the TraceOptions bit that marks a trace as sampled.
+pub const SAMPLED_FLAG: u32 = 1;
+
+/// DEBUG_FLAG is the TraceOptions bit that marks a trace for debugging.
+///
+/// It is set by formats such as B3 that carry a debug flag. It sits outside
+/// the low byte so the binary and traceparent formats never propagate it.
+pub const DEBUG_FLAG: u32 = 1 << 8;
+
 /// TraceOptions contains options associated with a trace span.
 #[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
 pub struct TraceOptions(pub u32); // ???
@@ -273,22 +614,33 @@ pub struct TraceOptions(pub u32); // ???
 impl TraceOptions {
     /// Whether the trace should be sampled.
     pub fn is_sampled(self) -> bool {
-        self.0 & 1 == 1
+        self.0 & SAMPLED_FLAG == SAMPLED_FLAG
+    }
+
+    /// Whether the trace has been marked for debugging.
+    pub fn is_debug(self) -> bool {
+        self.0 & DEBUG_FLAG == DEBUG_FLAG
     }
 }
 
 /// All available span kinds. Span kind must be either one of these values.
-#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
 pub enum SpanKind {
+    /// The kind of the span is unknown.
+    #[default]
     Unspecified = 1,
+    /// The span covers server-side handling of a request.
     Server,
+    /// The span covers the client-side of a request.
     Client,
-}
-
-impl Default for SpanKind {
-    fn default() -> SpanKind {
-        SpanKind::Unspecified
-    }
+    /// The span covers sending a message to a broker, such as a Kafka
+    /// producer. Unlike a client span, it doesn't wait for a response.
+    Producer,
+    /// The span covers receiving and handling a message from a broker, such
+    /// as a Kafka consumer.
+    Consumer,
+    /// The span covers in-process work that doesn't cross a process boundary.
+    Internal,
 }
 
 /// StartOptions contains options concerning how a span is started.
@@ -307,10 +659,26 @@ pub struct StartOptions {
 
     /// SpanKind represents the kind of a span. Defaults to Unspecified.
     pub span_kind: SpanKind,
+
+    /// start_time overrides the time the span started at. Defaults to the
+    /// current time.
+    pub start_time: Option<SystemTime>,
+}
+
+impl StartOptions {
+    /// from_options returns the StartOptions produced by applying each
+    /// StartOption in turn.
+    pub(crate) fn from_options(o: &[StartOption]) -> Self {
+        let mut opts = StartOptions::default();
+        for op in o {
+            op(&mut opts);
+        }
+        opts
+    }
 }
 
 /// StartOption applies changes to StartOptions.
-type StartOption = Box<dyn Fn(&mut StartOptions)>;
+pub(crate) type StartOption = Box<dyn Fn(&mut StartOptions)>;
 
 /// with_span_kind makes new spans to be created with the given kind.
 pub fn with_span_kind(span_kind: SpanKind) -> StartOption {
@@ -322,6 +690,12 @@ pub fn with_sampler(sampler: Sampler) -> StartOption {
     Box::new(move |o: &mut StartOptions| o.sampler = Some(Arc::clone(&sampler)))
 }
 
+/// with_start_time makes new spans to be created with the given start time,
+/// for spans recorded after the fact.
+pub fn with_start_time(start_time: SystemTime) -> StartOption {
+    Box::new(move |o: &mut StartOptions| o.start_time = Some(start_time))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -329,21 +703,14 @@ mod tests {
     use std::sync::Mutex;
 
     use crate::export::{register_exporter, unregister_exporter, Exporter};
+    use crate::propagation::trace_context::TraceContextFormat;
+    use crate::sampling::always_sample;
+    use crate::test_util::TestExporter;
     use crate::tracestate::{Key, Value};
 
     const TID: TraceID = TraceID([1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 4, 8, 16, 32, 64, 128]);
     const SID: SpanID = SpanID([1, 2, 4, 8, 16, 32, 64, 128]);
 
-    struct TestExporter {
-        pub exported_spans: Mutex<Vec<SpanData>>,
-    }
-
-    impl Exporter for TestExporter {
-        fn export_span(&self, s: &SpanData) {
-            self.exported_spans.lock().unwrap().push(s.clone())
-        }
-    }
-
     #[test]
     fn id_string_represenation() {
         assert_eq!(format!("{}", TID), "01020304050607080102040810204080");
@@ -534,6 +901,45 @@ mod tests {
     #[test]
     fn sampler_has_no_effect_on_local_children() {}
 
+    #[test]
+    fn start_child_without_context() {
+        use crate::sampling::{always_sample, never_sample};
+
+        let (_, sampled) = start_span(
+            &Context::background().freeze(),
+            "parent",
+            &[with_sampler(always_sample())],
+        );
+        let child = sampled.start_child("child", &[]);
+        assert_eq!(child.span_context.trace_id, sampled.span_context.trace_id);
+        assert!(child.span_context.span_id != sampled.span_context.span_id);
+        assert!(child.span_context.is_sampled());
+        let data = child.make_span_data().unwrap();
+        assert_eq!(data.name, "child");
+        assert_eq!(data.parent_span_id, Some(sampled.span_context.span_id));
+        assert!(!data.has_remote_parent);
+
+        let grandchild = start_child_span("grandchild", child.span_context(), &[]);
+        assert_eq!(
+            grandchild.span_context.trace_id,
+            sampled.span_context.trace_id
+        );
+        assert!(grandchild.is_recording_events());
+
+        let (_, unsampled) = start_span(
+            &Context::background().freeze(),
+            "parent",
+            &[with_sampler(never_sample())],
+        );
+        let child = unsampled.start_child("child", &[]);
+        assert_eq!(child.span_context.trace_id, unsampled.span_context.trace_id);
+        assert!(!child.span_context.is_sampled());
+        assert!(!child.is_recording_events());
+
+        let child = unsampled.start_child("child", &[with_sampler(always_sample())]);
+        assert!(child.span_context.is_sampled());
+    }
+
     #[test]
     fn probability_sampler_samples_approximately() {
         use crate::sampling::probability_sampler;
@@ -549,7 +955,7 @@ mod tests {
             }
         }
         // potentially flakey, but unavoidable.
-        if exported < 200 || exported > 400 {
+        if !(200..=400).contains(&exported) {
             panic!(
                 "number of spans out of expected bounds, want approx 30% got {}",
                 (exported as f64) * 0.1
@@ -626,17 +1032,15 @@ mod tests {
     mod span_data {
         use super::*;
 
-        use std::sync::Mutex;
+        use std::time::Duration;
 
         use crate::export::{register_exporter, unregister_exporter};
 
         type StartSpanHelper = Box<dyn Fn(&[StartOption]) -> Span>;
         type EndSpanHelper = Box<dyn Fn(Span) -> SpanData>;
 
-        fn make_helpers(name: &'static str, then: Instant) -> (StartSpanHelper, EndSpanHelper) {
-            let te = Arc::new(TestExporter {
-                exported_spans: Mutex::new(Vec::new()),
-            });
+        fn make_helpers(name: &'static str, then: SystemTime) -> (StartSpanHelper, EndSpanHelper) {
+            let te = Arc::new(TestExporter::default());
 
             let start_span_helper = |o: &[StartOption]| {
                 let (_, span) = start_span_with_remote_parent(
@@ -690,7 +1094,7 @@ mod tests {
 
         #[test]
         fn span_kind() {
-            let then = Instant::now();
+            let then = SystemTime::now();
             struct TestCase {
                 name: &'static str,
                 start_options: Vec<StartOption>,
@@ -720,6 +1124,10 @@ mod tests {
                         message_events: Vec::new(),
                         status: None,
                         links: Vec::new(),
+                        dropped_attribute_count: 0,
+                        dropped_annotation_count: 0,
+                        dropped_message_event_count: 0,
+                        dropped_link_count: 0,
                     },
                 },
                 TestCase {
@@ -744,6 +1152,10 @@ mod tests {
                         message_events: Vec::new(),
                         status: None,
                         links: Vec::new(),
+                        dropped_attribute_count: 0,
+                        dropped_annotation_count: 0,
+                        dropped_message_event_count: 0,
+                        dropped_link_count: 0,
                     },
                 },
                 TestCase {
@@ -768,6 +1180,10 @@ mod tests {
                         message_events: Vec::new(),
                         status: None,
                         links: Vec::new(),
+                        dropped_attribute_count: 0,
+                        dropped_annotation_count: 0,
+                        dropped_message_event_count: 0,
+                        dropped_link_count: 0,
                     },
                 },
             ];
@@ -780,9 +1196,31 @@ mod tests {
             }
         }
 
+        #[test]
+        fn all_span_kinds() {
+            for kind in &[
+                SpanKind::Unspecified,
+                SpanKind::Server,
+                SpanKind::Client,
+                SpanKind::Producer,
+                SpanKind::Consumer,
+                SpanKind::Internal,
+            ] {
+                let (_, span) = start_span(
+                    &Context::background().freeze(),
+                    "span kind",
+                    &[
+                        with_sampler(crate::sampling::always_sample()),
+                        with_span_kind(*kind),
+                    ],
+                );
+                assert_eq!(span.make_span_data().unwrap().span_kind, *kind);
+            }
+        }
+
         #[test]
         fn set_span_attributes() {
-            let then = Instant::now();
+            let then = SystemTime::now();
             let (start_span_helper, end_span_helper) = make_helpers("span attributes", then);
 
             let mut attributes = HashMap::new();
@@ -813,18 +1251,318 @@ mod tests {
                 message_events: Vec::new(),
                 status: None,
                 links: Vec::new(),
+                dropped_attribute_count: 0,
+                dropped_annotation_count: 0,
+                dropped_message_event_count: 0,
+                dropped_link_count: 0,
             };
             assert_eq!(got, want);
         }
 
-        //TODO: max attributes per span
+        #[test]
+        fn add_span_attributes_merges() {
+            let then = SystemTime::now();
+            let (start_span_helper, end_span_helper) = make_helpers("merged attributes", then);
+
+            let mut span = start_span_helper(&[]);
+            span.add_attributes(vec![
+                (
+                    String::from("key1"),
+                    AttributeValue::StringAttribute(String::from("value1")),
+                ),
+                (String::from("key2"), AttributeValue::BoolAttribute(false)),
+            ]);
+            span.add_attributes(vec![
+                (String::from("key2"), AttributeValue::BoolAttribute(true)),
+                (String::from("key3"), AttributeValue::Int64Attribute(3)),
+            ]);
+            let got = end_span_helper(span);
+
+            let mut want = HashMap::new();
+            want.insert(
+                String::from("key1"),
+                AttributeValue::StringAttribute(String::from("value1")),
+            );
+            want.insert(String::from("key2"), AttributeValue::BoolAttribute(true));
+            want.insert(String::from("key3"), AttributeValue::Int64Attribute(3));
+            assert_eq!(got.attributes, want);
+        }
+
+        #[test]
+        fn annotations() {
+            let then = SystemTime::now();
+            let (start_span_helper, end_span_helper) = make_helpers("annotations", then);
+
+            let mut span = start_span_helper(&[]);
+            span.annotate("first");
+            span.annotate_with_attributes(
+                "second",
+                vec![(String::from("key1"), AttributeValue::BoolAttribute(true))],
+            );
+            let got = end_span_helper(span);
+
+            assert_eq!(got.annotations.len(), 2);
+            assert_eq!(got.annotations[0].message, "first");
+            assert!(got.annotations[0].attributes.is_empty());
+            assert_eq!(got.annotations[1].message, "second");
+            assert_eq!(
+                got.annotations[1].attributes.get("key1"),
+                Some(&AttributeValue::BoolAttribute(true))
+            );
+            assert!(got.annotations[0].time >= got.start_time);
+            assert!(got.annotations[1].time >= got.annotations[0].time);
+        }
+
+        #[test]
+        fn message_events() {
+            let then = SystemTime::now();
+            let (start_span_helper, end_span_helper) = make_helpers("message events", then);
+
+            let mut span = start_span_helper(&[]);
+            span.add_message_send_event(1, 200, 100);
+            span.add_message_receive_event(1, 300, 150);
+            let got = end_span_helper(span);
+
+            let got: Vec<_> = got
+                .message_events
+                .iter()
+                .map(|e| {
+                    (
+                        e.event_type.clone(),
+                        e.message_id,
+                        e.uncompressed_byte_size,
+                        e.compressed_byte_size,
+                    )
+                })
+                .collect();
+            assert_eq!(
+                got,
+                vec![
+                    (MessageEventType::Sent, 1, 200, 100),
+                    (MessageEventType::Recv, 1, 300, 150),
+                ]
+            );
+        }
+
+        #[test]
+        fn event_times_are_offset_from_start_time() {
+            let before = SystemTime::now();
+            let (_, mut span) = start_span(
+                &Context::background().freeze(),
+                "event times",
+                &[with_sampler(crate::sampling::always_sample())],
+            );
+            span.annotate("first");
+            span.add_message_send_event(1, 2, 3);
+            let got = span.make_span_data().unwrap();
+
+            assert!(got.start_time >= before);
+            assert!(got.annotations[0].time >= got.start_time);
+            assert!(got.message_events[0].time >= got.annotations[0].time);
+            assert!(got.end_time.unwrap() >= got.message_events[0].time);
+        }
+
+        #[test]
+        fn explicit_start_and_end_times() {
+            let te = Arc::new(TestExporter::default());
+            let dyn_te: Arc<dyn Exporter + Send + Sync> = te.clone();
+
+            let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_546_300_800);
+            let end = start + Duration::from_millis(1500);
+            let (_, mut span) = start_span(
+                &Context::background().freeze(),
+                "retroactive",
+                &[
+                    with_sampler(crate::sampling::always_sample()),
+                    with_start_time(start),
+                ],
+            );
+            let trace_id = span.span_context().trace_id;
+            span.annotate("imported");
+
+            register_exporter(Arc::clone(&dyn_te));
+            span.end_with_time(end);
+            unregister_exporter(&dyn_te);
+
+            let exported = te.exported_spans.lock().unwrap();
+            let got = exported
+                .iter()
+                .find(|s| s.span_context.trace_id == trace_id)
+                .unwrap();
+            assert_eq!(got.start_time, start);
+            assert_eq!(got.end_time, Some(end));
+            // events are still recorded at the current time.
+            assert!(got.annotations[0].time > end);
+        }
+
+        #[test]
+        fn events_on_unsampled_span_are_dropped() {
+            let (_, mut span) = start_span(
+                &Context::background().freeze(),
+                "unsampled",
+                &[with_sampler(crate::sampling::never_sample())],
+            );
+            span.annotate("dropped");
+            span.add_message_send_event(1, 2, 3);
+            assert!(span.make_span_data().is_none());
+        }
+
+        #[test]
+        fn span_limits_drop_oldest() {
+            let limits = SpanLimits {
+                max_attributes_per_span: 2,
+                max_annotation_events_per_span: 2,
+                max_message_events_per_span: 1,
+                max_links_per_span: 0,
+            };
+            let (_, mut span) = start_span(
+                &Context::background().freeze(),
+                "span limits",
+                &[with_sampler(crate::sampling::always_sample())],
+            );
+            span.data.as_ref().unwrap().write().unwrap().limits = limits;
+
+            span.add_attributes(vec![
+                (String::from("key1"), AttributeValue::Int64Attribute(1)),
+                (String::from("key2"), AttributeValue::Int64Attribute(2)),
+            ]);
+            // setting key1 again makes key2 the least recently set.
+            span.add_attributes(vec![
+                (String::from("key1"), AttributeValue::Int64Attribute(10)),
+                (String::from("key3"), AttributeValue::Int64Attribute(3)),
+            ]);
+            for message in &["first", "second", "third"] {
+                span.annotate(message);
+            }
+            span.add_message_send_event(1, 10, 10);
+            span.add_message_receive_event(2, 20, 20);
+            span.add_link(Link {
+                trace_id: TID,
+                span_id: SID,
+                _type: crate::basetypes::LinkType::Parent,
+                attributes: HashMap::new(),
+            });
+
+            let got = span.make_span_data().unwrap();
+
+            let mut want_attributes = HashMap::new();
+            want_attributes.insert(String::from("key1"), AttributeValue::Int64Attribute(10));
+            want_attributes.insert(String::from("key3"), AttributeValue::Int64Attribute(3));
+            assert_eq!(got.attributes, want_attributes);
+            assert_eq!(got.dropped_attribute_count, 1);
+
+            let messages: Vec<_> = got.annotations.iter().map(|a| &a.message[..]).collect();
+            assert_eq!(messages, vec!["second", "third"]);
+            assert_eq!(got.dropped_annotation_count, 1);
+
+            assert_eq!(got.message_events.len(), 1);
+            assert_eq!(got.message_events[0].message_id, 2);
+            assert_eq!(got.dropped_message_event_count, 1);
+
+            assert!(got.links.is_empty());
+            assert_eq!(got.dropped_link_count, 1);
+        }
+    }
+
+    mod span_guard {
+        use super::*;
+
+        use std::panic;
+
+        fn exported_with_guard(
+            name: &'static str,
+            f: impl FnOnce(SpanGuard) + panic::UnwindSafe,
+        ) -> Vec<SpanData> {
+            let te = Arc::new(TestExporter::default());
+            let dyn_te: Arc<dyn Exporter + Send + Sync> = te.clone();
+            register_exporter(Arc::clone(&dyn_te));
+
+            let (_, guard) = start_span_guarded(
+                &Context::background().freeze(),
+                name,
+                &[with_sampler(crate::sampling::always_sample())],
+            );
+            let trace_id = guard.span_context().trace_id;
+            let _ = panic::catch_unwind(move || f(guard));
+            unregister_exporter(&dyn_te);
+
+            let exported = te.exported_spans.lock().unwrap();
+            exported
+                .iter()
+                .filter(|s| s.span_context.trace_id == trace_id)
+                .cloned()
+                .collect()
+        }
+
+        #[test]
+        fn ends_span_on_drop() {
+            let exported = exported_with_guard("guard drop", |mut guard| {
+                guard.annotate("guarded");
+            });
+            assert_eq!(exported.len(), 1);
+            assert_eq!(exported[0].name, "guard drop");
+            assert_eq!(exported[0].annotations[0].message, "guarded");
+            assert_eq!(exported[0].status, None);
+        }
+
+        #[test]
+        fn sets_status_on_panic() {
+            let exported = exported_with_guard("guard panic", |_guard| {
+                panic!("boom");
+            });
+            assert_eq!(exported.len(), 1);
+            assert_eq!(
+                exported[0].status.as_ref().map(|s| s.code.clone()),
+                Some(StatusCode::Internal)
+            );
+        }
+
+        #[test]
+        fn into_span_disarms_guard() {
+            let span = Arc::new(Mutex::new(None));
+            let handed_off = Arc::clone(&span);
+            let exported = exported_with_guard("guard handoff", move |guard| {
+                *handed_off.lock().unwrap() = Some(guard.into_span());
+            });
+            assert!(exported.is_empty());
+            assert!(span.lock().unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn remote_parent_without_span_id() {
+        let te = Arc::new(TestExporter::default());
+        let dyn_te: Arc<dyn Exporter + Send + Sync> = te.clone();
+
+        let parent = SpanContext {
+            trace_id: TID,
+            span_id: SpanID::default(),
+            trace_options: TraceOptions(1),
+            trace_state: None,
+        };
+        let (_, span) = start_span_with_remote_parent(
+            &Context::background().freeze(),
+            "remote parent without span id",
+            &parent,
+            &[],
+        );
+        register_exporter(Arc::clone(&dyn_te));
+        span.end();
+        unregister_exporter(&dyn_te);
+
+        let exported = te.exported_spans.lock().unwrap();
+        let got = exported
+            .iter()
+            .find(|s| s.name == "remote parent without span id")
+            .unwrap();
+        assert_eq!(got.span_context.trace_id, TID);
+        assert_eq!(got.parent_span_id, None);
+        assert!(got.has_remote_parent);
     }
 
     #[test]
     fn unregister_exporter_ends_exporting() {
-        let te = Arc::new(TestExporter {
-            exported_spans: Mutex::new(Vec::new()),
-        });
+        let te = Arc::new(TestExporter::default());
         let local_te = Arc::clone(&te);
         let dyn_te: Arc<dyn Exporter + Send + Sync> = local_te;
         register_exporter(Arc::clone(&dyn_te));
@@ -845,4 +1583,53 @@ mod tests {
         let exported = te.exported_spans.lock().unwrap();
         assert_eq!(exported.len(), 0,);
     }
+
+    #[test]
+    fn producer_span_propagates_to_consumer_span() {
+        let te = Arc::new(TestExporter::default());
+        let dyn_te: Arc<dyn Exporter + Send + Sync> = te.clone();
+        let ctx = Context::background().freeze();
+
+        let mut properties = HashMap::new();
+        let (_, producer_span) = start_producer_span(
+            &ctx,
+            "send orders",
+            &TraceContextFormat,
+            &mut properties,
+            &[with_sampler(always_sample())],
+        );
+        assert!(properties.contains_key("traceparent"));
+
+        let (consumer_ctx, consumer_span) = start_consumer_span(
+            &ctx,
+            "receive orders",
+            &TraceContextFormat,
+            &properties,
+            &[],
+        );
+        assert_eq!(
+            from_context(&consumer_ctx).unwrap().span_context(),
+            consumer_span.span_context()
+        );
+
+        let trace_id = producer_span.span_context().trace_id;
+        let producer_span_id = producer_span.span_context().span_id;
+        register_exporter(Arc::clone(&dyn_te));
+        producer_span.end();
+        consumer_span.end();
+        unregister_exporter(&dyn_te);
+
+        let exported = te.exported_spans.lock().unwrap();
+        let got: Vec<_> = exported
+            .iter()
+            .filter(|s| s.span_context.trace_id == trace_id)
+            .collect();
+        assert_eq!(got.len(), 2);
+        assert_eq!(got[0].name, "send orders");
+        assert_eq!(got[0].span_kind, SpanKind::Producer);
+        assert_eq!(got[1].name, "receive orders");
+        assert_eq!(got[1].span_kind, SpanKind::Consumer);
+        assert_eq!(got[1].parent_span_id, Some(producer_span_id));
+        assert!(got[1].has_remote_parent);
+    }
 }
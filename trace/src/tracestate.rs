@@ -1,5 +1,6 @@
-use std::collections::btree_map;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
 
 use lazy_static::lazy_static;
 use regex::{Regex, RegexSet};
@@ -18,9 +19,12 @@ const VALUE_FORMAT: &str = r"^[\x20-\x2b\x2d-\x3c\x3e-\x7e]{0,255}[\x21-\x2b\x2d
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
 pub struct Key(String);
 
+/// KeyValidationError is returned when a string is not a valid Key.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub enum KeyValidationError {
+    /// The key is longer than 256 characters.
     ExceedsMaxSize(String),
+    /// The key contains characters that are not allowed.
     DoesNotMatchRegex(String),
 }
 
@@ -46,14 +50,15 @@ impl std::error::Error for KeyValidationError {
 }
 
 impl Key {
+    /// try_new validates a string and returns it as a Key.
     pub fn try_new(key: &str) -> Result<Self, KeyValidationError> {
         lazy_static! {
             static ref KEY_VALIDATION_RE: RegexSet =
-                RegexSet::new(&[KEY_WITHOUT_VENDOR_FORMAT, KEY_WITH_VENDOR_FORMAT]).unwrap();
+                RegexSet::new([KEY_WITHOUT_VENDOR_FORMAT, KEY_WITH_VENDOR_FORMAT]).unwrap();
         }
         if key.len() > KEY_MAX_SIZE {
             Err(KeyValidationError::ExceedsMaxSize(key.to_string()))
-        } else if !KEY_VALIDATION_RE.is_match(&key) {
+        } else if !KEY_VALIDATION_RE.is_match(key) {
             Err(KeyValidationError::DoesNotMatchRegex(key.to_string()))
         } else {
             Ok(Key(key.to_string()))
@@ -66,9 +71,12 @@ impl Key {
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
 pub struct Value(String);
 
+/// ValueValidationError is returned when a string is not a valid Value.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub enum ValueValidationError {
+    /// The value is longer than 256 characters.
     ExceedsMaxSize(String),
+    /// The value contains characters that are not allowed.
     DoesNotMatchRegex(String),
 }
 
@@ -94,13 +102,14 @@ impl std::error::Error for ValueValidationError {
 }
 
 impl Value {
+    /// try_new validates a string and returns it as a Value.
     pub fn try_new(value: &str) -> Result<Self, ValueValidationError> {
         lazy_static! {
             static ref VALUE_VALIDATION_RE: Regex = Regex::new(VALUE_FORMAT).unwrap();
         }
         if value.len() > VALUE_MAX_SIZE {
             Err(ValueValidationError::ExceedsMaxSize(value.to_string()))
-        } else if !VALUE_VALIDATION_RE.is_match(&value) {
+        } else if !VALUE_VALIDATION_RE.is_match(value) {
             Err(ValueValidationError::DoesNotMatchRegex(value.to_string()))
         } else {
             Ok(Value(value.to_string()))
@@ -108,20 +117,46 @@ impl Value {
     }
 }
 
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 /// Tracestate represents tracing-system specific context in a list of key-value pairs. Tracestate allows different
 /// vendors propagate additional information and inter-operate with their legacy Id formats.
-// TODO(john|p=3|#go): diverged from Go by using a BTreeMap instead of a slice.
+///
+/// Entries are kept in the order they appear on the wire, most recently
+/// updated first.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
-pub struct Tracestate(BTreeMap<Key, Value>);
+pub struct Tracestate(Vec<Entry>);
 
 /// Entry represents one key-value pair in a list of key-value pair of Tracestate.
 // TODO(john|p=3|#go): diverged from Go by using newtypes and smart constructors.
 pub type Entry = (Key, Value);
 
+/// Error is returned when a Tracestate cannot be constructed or parsed.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub enum Error {
+    /// The Tracestate would contain more than 32 entries.
     MaxKeyValuePairsExceeded,
-    DuplicateKey { duplicate: Key },
+    /// The same key appears more than once.
+    DuplicateKey {
+        /// duplicate is the first key found more than once.
+        duplicate: Key,
+    },
+    /// A list member of a tracestate header is not of the form key=value.
+    MalformedListMember(String),
+    /// A list member of a tracestate header has an invalid key.
+    InvalidKey(KeyValidationError),
+    /// A list member of a tracestate header has an invalid value.
+    InvalidValue(ValueValidationError),
 }
 
 impl std::fmt::Display for Error {
@@ -137,20 +172,31 @@ impl std::fmt::Display for Error {
                 "requested entries contain duplicate key '{:?}'",
                 duplicate
             ),
+            Error::MalformedListMember(m) => {
+                write!(f, "list member '{}' is not of the form key=value", m)
+            }
+            Error::InvalidKey(e) => write!(f, "invalid list member: {}", e),
+            Error::InvalidValue(e) => write!(f, "invalid list member: {}", e),
         }
     }
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
+        match &self {
+            Error::InvalidKey(e) => Some(e),
+            Error::InvalidValue(e) => Some(e),
+            _ => None,
+        }
     }
 }
 
 impl Tracestate {
+    /// try_new returns a Tracestate containing the entries of parent, if
+    /// any, updated with the given entries.
     pub fn try_new(parent: Option<&Tracestate>, entries: &[Entry]) -> Result<Self, Error> {
         if parent.is_none() && entries.is_empty() {
-            return Ok(Tracestate(BTreeMap::new()));
+            return Ok(Tracestate(Vec::new()));
         }
 
         // TODO(john|p=3|#go): diverged from Go by validating entries
@@ -160,39 +206,107 @@ impl Tracestate {
             return Err(Error::DuplicateKey { duplicate });
         }
 
-        let mut tracestate = Tracestate(BTreeMap::new());
-
-        if let Some(parent) = parent {
-            tracestate
-                .0
-                .extend(parent.0.iter().map(|(k, v)| (k.clone(), v.clone())));
-        }
+        let mut tracestate = parent.cloned().unwrap_or_default();
 
         tracestate.add(entries)?;
 
         Ok(tracestate)
     }
 
-    pub fn entries(&self) -> btree_map::Iter<'_, Key, Value> {
-        self.0.iter()
+    /// entries returns an iterator over the key-value pairs of the Tracestate
+    /// in the order they appear in the tracestate header.
+    pub fn entries(&self) -> impl ExactSizeIterator<Item = (&Key, &Value)> {
+        self.0.iter().map(|(k, v)| (k, v))
+    }
+
+    /// get returns the value associated with key, if any.
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// insert adds an entry to the front of the Tracestate. If key is already
+    /// present its old entry is removed, so updated entries also move to the
+    /// front.
+    ///
+    /// If the Tracestate already holds the maximum number of entries, the
+    /// oldest entry is dropped from the end to make room.
+    pub fn insert(&mut self, key: Key, value: Value) {
+        self.delete(&key);
+        self.0.insert(0, (key, value));
+        self.0.truncate(MAX_KEY_VALUE_PAIRS);
+    }
+
+    /// delete removes the entry for key and returns its value, if any.
+    pub fn delete(&mut self, key: &Key) -> Option<Value> {
+        let idx = self.0.iter().position(|(k, _)| k == key)?;
+        Some(self.0.remove(idx).1)
     }
 
     fn add(&mut self, entries: &[Entry]) -> Result<(), Error> {
-        // TODO(john|p=3|#go): we diverged from Go here by checking the union of
-        // keys instead of deleting the recomputing max.
-        let combined: BTreeMap<Key, Value> = self
-            .0
-            .iter()
-            .chain(entries.iter().map(|(k, v)| (k, v)))
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect();
-
-        if combined.len() > MAX_KEY_VALUE_PAIRS {
-            Err(Error::MaxKeyValuePairsExceeded)
-        } else {
-            self.0 = combined;
-            Ok(())
+        for (key, _) in entries {
+            self.delete(key);
         }
+
+        if self.0.len() + entries.len() > MAX_KEY_VALUE_PAIRS {
+            return Err(Error::MaxKeyValuePairsExceeded);
+        }
+
+        self.0.splice(0..0, entries.iter().cloned());
+        Ok(())
+    }
+}
+
+/// Tracestate header format:
+///
+/// Header value: <list-member>[,<list-member>]...
+/// list-member: <key>=<value>
+///
+/// Optional whitespace (spaces and tabs) is allowed around list members and
+/// empty list members are ignored. A header may contain at most 32 list
+/// members and each key may appear only once.
+///
+/// Valid value example:
+///
+/// rojo=00f067aa0ba902b7,congo=t61rcWkgMzE
+///
+/// If any list member is invalid the whole header must be discarded, so
+/// parsing fails rather than returning the valid members.
+impl FromStr for Tracestate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let entries = s
+            .split(',')
+            .map(|member| member.trim_matches(|c| c == ' ' || c == '\t'))
+            .filter(|member| !member.is_empty())
+            .map(parse_list_member)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Tracestate::try_new(None, &entries)
+    }
+}
+
+fn parse_list_member(member: &str) -> Result<Entry, Error> {
+    let mut parts = member.splitn(2, '=');
+    match (parts.next(), parts.next()) {
+        (Some(key), Some(value)) => Ok((
+            Key::try_new(key).map_err(Error::InvalidKey)?,
+            Value::try_new(value).map_err(Error::InvalidValue)?,
+        )),
+        _ => Err(Error::MalformedListMember(member.to_string())),
+    }
+}
+
+/// Tracestate formats as a tracestate header value.
+impl fmt::Display for Tracestate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (key, value)) in self.entries().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}={}", key, value)?;
+        }
+        Ok(())
     }
 }
 
@@ -209,12 +323,6 @@ fn contains_duplciate_key(entries: &[Entry]) -> Option<Key> {
 mod test {
     use super::*;
 
-    impl Tracestate {
-        fn get(&self, key: &Key) -> Option<&Value> {
-            self.0.get(key)
-        }
-    }
-
     #[test]
     fn create_with_no_parent() {
         let key1 = Key::try_new("hello").unwrap();
@@ -283,9 +391,8 @@ mod test {
         let tracestate = Tracestate::try_new(Some(&parent), &[entry3]).unwrap();
 
         assert_eq!(tracestate.get(&key3), Some(&value3));
-        // TODO(john|p=1|#go|#spec): Entries come out in a different order.
-        assert_eq!(tracestate.entries().next(), Some((&key2, &value2)));
-        assert_eq!(tracestate.entries().last(), Some((&key3, &value3)));
+        assert_eq!(tracestate.entries().next(), Some((&key3, &value3)));
+        assert_eq!(tracestate.entries().last(), Some((&key2, &value2)));
         assert_eq!(tracestate.entries().len(), 2);
     }
 
@@ -344,7 +451,7 @@ mod test {
 
     #[test]
     fn invalid_key_length() {
-        let too_long: String = std::iter::repeat('a').take(KEY_MAX_SIZE + 1).collect();
+        let too_long: String = "a".repeat(KEY_MAX_SIZE + 1);
         assert_eq!(
             Key::try_new(&too_long),
             Err(KeyValidationError::ExceedsMaxSize(too_long.to_string()))
@@ -353,7 +460,7 @@ mod test {
 
     #[test]
     fn invalid_value_length() {
-        let too_long: String = std::iter::repeat('a').take(VALUE_MAX_SIZE + 1).collect();
+        let too_long: String = "a".repeat(VALUE_MAX_SIZE + 1);
         assert_eq!(
             Value::try_new(&too_long),
             Err(ValueValidationError::ExceedsMaxSize(too_long.to_string()))
@@ -370,7 +477,7 @@ mod test {
         let value = Value::try_new("world").unwrap();
         let entries: Vec<_> = keys.iter().cloned().map(|k| (k, value.clone())).collect();
 
-        let tracestate = Tracestate::try_new(None, &*entries);
+        let tracestate = Tracestate::try_new(None, &entries);
         assert_eq!(tracestate, Err(Error::MaxKeyValuePairsExceeded));
     }
 
@@ -391,7 +498,7 @@ mod test {
         let value = Value::try_new("world").unwrap();
         let entries: Vec<_> = keys.iter().cloned().map(|k| (k, value.clone())).collect();
 
-        let parent = Tracestate::try_new(None, &*entries).unwrap();
+        let parent = Tracestate::try_new(None, &entries).unwrap();
 
         let key = Key::try_new(&format!("a{}b", MAX_KEY_VALUE_PAIRS)).unwrap();
 
@@ -423,6 +530,140 @@ mod test {
         );
     }
 
+    #[test]
+    fn parse_header() {
+        let tracestate: Tracestate = "foo=bar , hello=world,,\tbaz=a b".parse().unwrap();
+
+        assert_eq!(
+            tracestate.get(&Key::try_new("foo").unwrap()),
+            Some(&Value::try_new("bar").unwrap())
+        );
+        assert_eq!(
+            tracestate.get(&Key::try_new("hello").unwrap()),
+            Some(&Value::try_new("world").unwrap())
+        );
+        assert_eq!(
+            tracestate.get(&Key::try_new("baz").unwrap()),
+            Some(&Value::try_new("a b").unwrap())
+        );
+        assert_eq!(tracestate.entries().len(), 3);
+    }
+
+    #[test]
+    fn parse_empty_header() {
+        let tracestate: Tracestate = " , ".parse().unwrap();
+
+        assert_eq!(tracestate.entries().len(), 0);
+        assert_eq!(tracestate.to_string(), "");
+    }
+
+    #[test]
+    fn parse_invalid_header() {
+        let test_cases = vec![
+            ("foo", Error::MalformedListMember("foo".to_string())),
+            (
+                "foo=bar,Foo=bar",
+                Error::InvalidKey(KeyValidationError::DoesNotMatchRegex("Foo".to_string())),
+            ),
+            (
+                "foo=bar,baz=",
+                Error::InvalidValue(ValueValidationError::DoesNotMatchRegex("".to_string())),
+            ),
+            (
+                "foo=bar,baz=a=b",
+                Error::InvalidValue(ValueValidationError::DoesNotMatchRegex("a=b".to_string())),
+            ),
+            (
+                "foo=bar,foo=baz",
+                Error::DuplicateKey {
+                    duplicate: Key::try_new("foo").unwrap(),
+                },
+            ),
+        ];
+
+        for (header, want) in test_cases {
+            assert_eq!(header.parse::<Tracestate>(), Err(want), "{}", header);
+        }
+    }
+
+    #[test]
+    fn parse_header_with_over_limit_list_members() {
+        let header = (0..=MAX_KEY_VALUE_PAIRS)
+            .map(|i| format!("a{}b=world", i))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        assert_eq!(
+            header.parse::<Tracestate>(),
+            Err(Error::MaxKeyValuePairsExceeded)
+        );
+    }
+
+    #[test]
+    fn header_roundtrip() {
+        let header = "foo=bar@1,bar=baz";
+        let tracestate: Tracestate = header.parse().unwrap();
+
+        assert_eq!(tracestate.to_string(), header);
+    }
+
+    #[test]
+    fn insert_new_key_at_front() {
+        let mut tracestate: Tracestate = "foo=bar,hello=world".parse().unwrap();
+
+        tracestate.insert(Key::try_new("bar").unwrap(), Value::try_new("baz").unwrap());
+
+        assert_eq!(tracestate.to_string(), "bar=baz,foo=bar,hello=world");
+    }
+
+    #[test]
+    fn insert_existing_key_moves_to_front() {
+        let mut tracestate: Tracestate = "foo=bar,hello=world,bar=baz".parse().unwrap();
+
+        tracestate.insert(
+            Key::try_new("hello").unwrap(),
+            Value::try_new("there").unwrap(),
+        );
+
+        assert_eq!(tracestate.to_string(), "hello=there,foo=bar,bar=baz");
+    }
+
+    #[test]
+    fn insert_over_limit_drops_oldest() {
+        let keys = (0..MAX_KEY_VALUE_PAIRS)
+            .map(|i| Key::try_new(&format!("a{}b", i)))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let value = Value::try_new("world").unwrap();
+        let entries: Vec<_> = keys.iter().cloned().map(|k| (k, value.clone())).collect();
+
+        let mut tracestate = Tracestate::try_new(None, &entries).unwrap();
+        let key = Key::try_new("new").unwrap();
+        tracestate.insert(key.clone(), value.clone());
+
+        assert_eq!(tracestate.entries().len(), MAX_KEY_VALUE_PAIRS);
+        assert_eq!(tracestate.entries().next(), Some((&key, &value)));
+        assert_eq!(tracestate.get(&keys[MAX_KEY_VALUE_PAIRS - 1]), None);
+        assert_eq!(
+            tracestate.entries().last(),
+            Some((&keys[MAX_KEY_VALUE_PAIRS - 2], &value))
+        );
+    }
+
+    #[test]
+    fn delete_key() {
+        let mut tracestate: Tracestate = "foo=bar,hello=world,bar=baz".parse().unwrap();
+
+        let key = Key::try_new("hello").unwrap();
+        assert_eq!(
+            tracestate.delete(&key),
+            Some(Value::try_new("world").unwrap())
+        );
+        assert_eq!(tracestate.delete(&key), None);
+        assert_eq!(tracestate.to_string(), "foo=bar,bar=baz");
+    }
+
     //TODO(john|p=5|#go|#spec): No concept of nil slice or variadic arguments.
     //fn entries_with_none()
 }